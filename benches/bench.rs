@@ -53,6 +53,12 @@ impl OrderInterface for BenchOrder {
         self.remaining -= quantity;
     }
 
+    fn amend(&mut self, price: u64, quantity: u64) {
+        self.price = price;
+        self.quantity = quantity;
+        self.remaining = quantity;
+    }
+
     fn owner(&self) -> &u64 {
         &self.id
     }
@@ -97,7 +103,10 @@ fn bench_eval_insert(c: &mut Criterion) {
             },
             |(ob, order)| {
                 eval.reset();
-                black_box(eval.eval_insert(ob, black_box(order.take().unwrap())).count());
+                black_box(
+                    eval.eval_insert(ob, black_box(order.take().unwrap()))
+                        .count(),
+                );
             },
             BatchSize::LargeInput,
         );
@@ -113,7 +122,10 @@ fn bench_eval_insert(c: &mut Criterion) {
             },
             |(ob, order)| {
                 eval.reset();
-                black_box(eval.eval_insert(ob, black_box(order.take().unwrap())).count());
+                black_box(
+                    eval.eval_insert(ob, black_box(order.take().unwrap()))
+                        .count(),
+                );
             },
             BatchSize::LargeInput,
         );
@@ -129,7 +141,10 @@ fn bench_eval_insert(c: &mut Criterion) {
             },
             |(ob, order)| {
                 eval.reset();
-                black_box(eval.eval_insert(ob, black_box(order.take().unwrap())).count());
+                black_box(
+                    eval.eval_insert(ob, black_box(order.take().unwrap()))
+                        .count(),
+                );
             },
             BatchSize::LargeInput,
         );
@@ -313,7 +328,9 @@ fn bench_eval_match(c: &mut Criterion) {
             |(ob, buy)| {
                 eval.reset();
                 eval.eval_insert(ob, black_box(buy.take().unwrap()))
-                    .for_each(|i| { black_box(i); });
+                    .for_each(|i| {
+                        black_box(i);
+                    });
             },
             BatchSize::LargeInput,
         );
@@ -337,7 +354,9 @@ fn bench_eval_match(c: &mut Criterion) {
             |(ob, buy)| {
                 eval.reset();
                 eval.eval_insert(ob, black_box(buy.take().unwrap()))
-                    .for_each(|i| { black_box(i); });
+                    .for_each(|i| {
+                        black_box(i);
+                    });
             },
             BatchSize::LargeInput,
         );
@@ -361,7 +380,9 @@ fn bench_eval_match(c: &mut Criterion) {
             |(ob, buy)| {
                 eval.reset();
                 eval.eval_insert(ob, black_box(buy.take().unwrap()))
-                    .for_each(|i| { black_box(i); });
+                    .for_each(|i| {
+                        black_box(i);
+                    });
             },
             BatchSize::LargeInput,
         );
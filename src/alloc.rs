@@ -0,0 +1,103 @@
+use crate::order::OrderInterface;
+
+/// How a taker's fill is shared among the makers at a price level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Allocation {
+    /// Strict price-time priority: makers fill in queue order.
+    #[default]
+    Fifo,
+    /// Makers fill in proportion to their available quantity, rounded down, with the
+    /// rounding remainder handed out in queue order. If `top_order`, the first order in
+    /// the queue at the first level swept is filled in full before the split.
+    ProRata { top_order: bool },
+}
+
+/// Lead market maker entitlement: resting orders of `owners` share up to
+/// `share.0 / share.1` of each taker's quantity at the first level it reaches, in queue
+/// order, ahead of the book's allocation. The rest of the fill, including any left for
+/// those orders, is allocated as usual.
+pub struct LeadMakers<O: OrderInterface> {
+    pub owners: Vec<O::O>,
+    /// (Numerator, Denominator), at most one with a non-zero denominator; the
+    /// entitlement is rounded down.
+    pub share: (O::N, O::N),
+}
+
+/// Splits `quantity` across makers of the given `sizes` (in queue order) pro rata,
+/// writing each maker's share to `allocs`. Shares never exceed sizes and sum to
+/// `quantity` or the total size, whichever is smaller.
+pub(crate) fn pro_rata<O: OrderInterface>(
+    mut quantity: O::N,
+    sizes: &[O::N],
+    top_order: bool,
+    allocs: &mut Vec<O::N>,
+) {
+    let zero = O::N::default();
+    allocs.clear();
+    allocs.resize(sizes.len(), zero);
+    if top_order && let Some(&size) = sizes.first() {
+        allocs[0] = quantity.min(size);
+        quantity -= allocs[0];
+    }
+
+    let mut total = zero;
+    for (&size, &alloc) in sizes.iter().zip(allocs.iter()) {
+        total += size - alloc;
+    }
+    if quantity == zero || total == zero {
+        return;
+    }
+    if quantity >= total {
+        allocs.copy_from_slice(sizes);
+        return;
+    }
+
+    let mut left = quantity;
+    for (&size, alloc) in sizes.iter().zip(allocs.iter_mut()) {
+        let share = (size - *alloc) * quantity / total;
+        *alloc += share;
+        left -= share;
+    }
+    // Rounding remainder, FIFO.
+    for (&size, alloc) in sizes.iter().zip(allocs.iter_mut()) {
+        if left == zero {
+            break;
+        }
+        let extra = left.min(size - *alloc);
+        *alloc += extra;
+        left -= extra;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::TestOrder;
+
+    #[test]
+    fn test_pro_rata_split() {
+        let mut allocs = Vec::new();
+        pro_rata::<TestOrder>(100, &[200, 100, 100], false, &mut allocs);
+        assert_eq!(allocs, vec![50, 25, 25]);
+
+        // 10 * 1/3 rounds down to 3 each; the remainder goes FIFO.
+        pro_rata::<TestOrder>(10, &[30, 30, 30], false, &mut allocs);
+        assert_eq!(allocs, vec![4, 3, 3]);
+
+        pro_rata::<TestOrder>(500, &[30, 20], false, &mut allocs);
+        assert_eq!(allocs, vec![30, 20]);
+
+        pro_rata::<TestOrder>(0, &[30, 20], false, &mut allocs);
+        assert_eq!(allocs, vec![0, 0]);
+    }
+
+    #[test]
+    fn test_pro_rata_top_order() {
+        let mut allocs = Vec::new();
+        pro_rata::<TestOrder>(100, &[40, 100, 200], true, &mut allocs);
+        assert_eq!(allocs, vec![40, 20, 40]);
+
+        pro_rata::<TestOrder>(30, &[40, 100], true, &mut allocs);
+        assert_eq!(allocs, vec![30, 0]);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::{
+    eval::{Instruction, Msg},
+    level::Level,
+    order::OrderInterface,
+    side::Side,
+};
+
+/// Call auction state. In a call phase orders rest without matching, even if they
+/// cross, until the book is uncrossed. Market orders are held here in arrival order, off
+/// the bid/ask sides; market-to-limit orders are held as market orders.
+pub struct Auction<O: OrderInterface> {
+    market: Vec<O>,
+}
+
+impl<O: OrderInterface> Default for Auction<O> {
+    fn default() -> Self {
+        Self { market: Vec::new() }
+    }
+}
+
+impl<O: OrderInterface> Auction<O> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.market.len()
+    }
+
+    #[inline]
+    pub fn get(&self, order_id: &O::I) -> Option<&O> {
+        self.market.iter().find(|order| order.id() == order_id)
+    }
+
+    /// Holds a market order for the uncross.
+    #[inline]
+    pub fn insert(&mut self, order: O) {
+        self.market.push(order);
+    }
+
+    #[inline]
+    pub fn remove(&mut self, order_id: &O::I) -> Option<O> {
+        let pos = self
+            .market
+            .iter()
+            .position(|order| order.id() == order_id)?;
+        Some(self.market.remove(pos))
+    }
+
+    /// Fills a held market order; returns true (and drops it) once fully filled.
+    pub fn fill(&mut self, order_id: &O::I, quantity: O::N) -> bool {
+        let pos = self
+            .market
+            .iter()
+            .position(|order| order.id() == order_id)
+            .unwrap();
+        self.market[pos].fill(quantity);
+        if self.market[pos].remaining() == O::N::default() {
+            self.market.remove(pos);
+            return true;
+        }
+        false
+    }
+
+    /// Returns held market orders on one side, in arrival order.
+    #[inline]
+    fn market(&self, is_buy: bool) -> impl Iterator<Item = &O> {
+        self.market
+            .iter()
+            .filter(move |order| order.is_buy() == is_buy)
+    }
+}
+
+/// Frequent batch auction schedule: batches end at multiples of `interval` on the book
+/// clock.
+pub(crate) struct Batch {
+    pub interval: u64,
+    /// Index of the current batch (clock time / interval).
+    pub current: u64,
+}
+
+impl Batch {
+    /// Moves to the batch containing `now`; returns true if the current one ended.
+    #[inline]
+    pub fn roll(&mut self, now: u64) -> bool {
+        let index = now / self.interval;
+        if index > self.current {
+            self.current = index;
+            return true;
+        }
+        false
+    }
+}
+
+/// (Price, Volume, Demand, Supply) at a candidate uncross price.
+type Candidate<N> = (N, N, N, N);
+
+/// Returns the quantity `order` brings to the uncross; all-or-none orders sit out.
+#[inline(always)]
+fn auction_quantity<O: OrderInterface>(order: &O) -> O::N {
+    if order.all_or_none() {
+        O::N::default()
+    } else {
+        order.remaining()
+    }
+}
+
+/// Returns the total quantity at `level` taking part in the uncross, hidden included.
+#[inline]
+fn level_quantity<O: OrderInterface>(level: &Level<O>) -> O::N {
+    let mut total = O::N::default();
+    for order in level.iter() {
+        total += auction_quantity(order);
+    }
+    total
+}
+
+/// Returns the uncross (price, volume), or `None` if nothing would trade.
+///
+/// Candidate prices are the resting limit prices. The uncross price maximizes executed
+/// volume; ties go to the smallest surplus (unmatched quantity at the price), then to
+/// market pressure: the highest price if every tied price has a buy surplus, the lowest
+/// if every one has a sell surplus. Otherwise the price closest to `reference` wins,
+/// the lowest without one.
+pub(crate) fn equilibrium<O: OrderInterface>(
+    bids: &Side<O>,
+    asks: &Side<O>,
+    auction: &Auction<O>,
+    reference: Option<O::N>,
+) -> Option<(O::N, O::N)> {
+    let zero = O::N::default();
+    let mut market_buy = zero;
+    for order in auction.market(true) {
+        market_buy += order.remaining();
+    }
+    let mut market_sell = zero;
+    for order in auction.market(false) {
+        market_sell += order.remaining();
+    }
+
+    // (Price, Bid Quantity, Ask Quantity), lowest price first.
+    let mut levels: Vec<(O::N, O::N, O::N)> = Vec::new();
+    let mut total_bid = zero;
+    for level in bids.iter() {
+        let quantity = level_quantity(level);
+        total_bid += quantity;
+        levels.push((level.price(), quantity, zero));
+    }
+    for level in asks.iter() {
+        levels.push((level.price(), zero, level_quantity(level)));
+    }
+    levels.sort_by_key(|&(price, _, _)| price);
+    levels.dedup_by(|later, earlier| {
+        if later.0 != earlier.0 {
+            return false;
+        }
+        earlier.1 += later.1;
+        earlier.2 += later.2;
+        true
+    });
+
+    let mut candidates: Vec<Candidate<O::N>> = Vec::with_capacity(levels.len());
+    let mut bid_below = zero;
+    let mut ask_through = zero;
+    for &(price, bid, ask) in &levels {
+        ask_through += ask;
+        let demand = market_buy + total_bid - bid_below;
+        let supply = market_sell + ask_through;
+        candidates.push((price, demand.min(supply), demand, supply));
+        bid_below += bid;
+    }
+
+    let volume = candidates.iter().map(|&(_, volume, _, _)| volume).max()?;
+    if volume == zero {
+        return None;
+    }
+    let diff = |a: O::N, b: O::N| if a > b { a - b } else { b - a };
+    candidates.retain(|&(_, v, _, _)| v == volume);
+    let surplus = candidates
+        .iter()
+        .map(|&(_, _, demand, supply)| diff(demand, supply))
+        .min()?;
+    candidates.retain(|&(_, _, demand, supply)| diff(demand, supply) == surplus);
+
+    let price = if candidates
+        .iter()
+        .all(|&(_, _, demand, supply)| demand > supply)
+    {
+        candidates.last()?.0
+    } else if candidates
+        .iter()
+        .all(|&(_, _, demand, supply)| demand < supply)
+    {
+        candidates.first()?.0
+    } else {
+        match reference {
+            Some(reference) => {
+                candidates
+                    .iter()
+                    .min_by_key(|&&(price, _, _, _)| diff(price, reference))?
+                    .0
+            }
+            None => candidates.first()?.0,
+        }
+    };
+    Some((price, volume))
+}
+
+/// Returns the instructions that uncross the book at the equilibrium price: fills for
+/// every matched order at that price, then deletes for unfilled held market orders.
+///
+/// Each side fills market orders first, then limit orders best price first, in queue
+/// order within a level (hidden orders last). Icebergs fill slice by slice.
+pub(crate) fn uncross<O: OrderInterface>(
+    bids: &Side<O>,
+    asks: &Side<O>,
+    auction: &Auction<O>,
+    reference: Option<O::N>,
+) -> Vec<Instruction<O>> {
+    let mut out = Vec::new();
+    let mut leftover = Vec::new();
+    let (price, volume) = equilibrium(bids, asks, auction, reference).unwrap_or_default();
+    allocate(bids, auction, true, price, volume, &mut out, &mut leftover);
+    allocate(asks, auction, false, price, volume, &mut out, &mut leftover);
+    out.append(&mut leftover);
+    out
+}
+
+/// Fills `volume` on one side at `price`, pushing unfilled market orders to `leftover`.
+fn allocate<O: OrderInterface>(
+    side: &Side<O>,
+    auction: &Auction<O>,
+    is_buy: bool,
+    price: O::N,
+    volume: O::N,
+    out: &mut Vec<Instruction<O>>,
+    leftover: &mut Vec<Instruction<O>>,
+) {
+    let zero = O::N::default();
+    let mut left = volume;
+    for order in auction.market(is_buy) {
+        let fill = left.min(order.remaining());
+        if fill > zero {
+            left -= fill;
+            out.push(Instruction::Fill(
+                order.id().clone(),
+                order.owner().clone(),
+                price,
+                fill,
+                false,
+            ));
+        }
+        if fill < order.remaining() {
+            let msg = if fill > zero {
+                Msg::MarketLeftover
+            } else {
+                Msg::MarketNoFill
+            };
+            leftover.push(Instruction::Delete(order.id().clone(), msg));
+        }
+    }
+
+    for level in side.iter() {
+        if left == zero || (is_buy && level.price() < price) || (!is_buy && level.price() > price) {
+            break;
+        }
+        for order in level.iter() {
+            if left == zero {
+                break;
+            }
+            let mut fill = left.min(auction_quantity(order));
+            if fill == zero {
+                continue;
+            }
+            left -= fill;
+            // An iceberg fill may not exceed its displayed slice.
+            let (mut slice, display) = match order.display_quantity() {
+                Some(display) if !order.hidden() => (level.visible(order), display),
+                _ => (fill, fill),
+            };
+            let mut rest = order.remaining();
+            while fill > zero {
+                let qty = fill.min(slice);
+                out.push(Instruction::Fill(
+                    order.id().clone(),
+                    order.owner().clone(),
+                    price,
+                    qty,
+                    false,
+                ));
+                fill -= qty;
+                rest -= qty;
+                slice = display.min(rest);
+            }
+        }
+    }
+}
@@ -0,0 +1,14 @@
+/// Time source injected into the book for time-based order handling.
+///
+/// Timestamps are opaque `u64` ticks (e.g. nanoseconds since epoch, or a simulation
+/// step); the book only compares them. Implemented for any `Fn() -> u64`.
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    #[inline(always)]
+    fn now(&self) -> u64 {
+        self()
+    }
+}
@@ -7,18 +7,58 @@
 //! - **Apply** (in `ob`) takes each instruction and mutates the book; call it after eval.
 
 use crate::{
+    alloc::{Allocation, pro_rata},
+    auction,
+    expiry::Expiries,
     hash::FxHashMap,
+    level::Level,
     ob::OrderBook,
-    order::{OrderInterface, STP, TIF},
+    order::{OrderInterface, OrderType, STP, TIF},
+    phase::Phase,
+    protect::{BandAction, CollarAction},
 };
+use std::collections::VecDeque;
 
 /// An operation to apply to the orderbook.
 pub enum Op<O: OrderInterface> {
     Insert(O),
     Delete(O::I),
+    /// Atomic cancel/replace of a resting order. `new_quantity` is the new original
+    /// quantity; quantity already filled is carried over. A price change or quantity
+    /// increase loses time priority (and may match); a pure decrease keeps it.
+    Replace {
+        id: O::I,
+        new_price: O::N,
+        new_quantity: O::N,
+    },
+    /// Reduce a resting order's remaining quantity by `quantity`, keeping queue position.
+    /// Reducing by its full remaining quantity cancels the order.
+    Reduce {
+        id: O::I,
+        quantity: O::N,
+    },
+    /// An order released by the book: a triggered stop, or an order held by the speed
+    /// bump (see `OrderBook::pop_pending`). Evaluated as an insert without re-checking
+    /// the stop price or delaying it again.
+    Trigger(O),
+    /// Move the book to a new trading phase (see `Phase`).
+    Phase(Phase),
+    /// Uncross orders collected in a call phase or frequent batch auction (see
+    /// `OrderBook::uncross`).
+    Uncross,
+    /// Atomically replace `owner`'s quotes with `bid` and `ask`; `None` pulls that side.
+    /// A side whose order keeps the id of the owner's current quote is amended as with
+    /// `Replace`, so a same-price quantity decrease keeps priority; otherwise the current
+    /// quote is cancelled and the new order inserted (and may match). The current quotes
+    /// are pulled or amended before either new side is matched.
+    Quote {
+        owner: O::O,
+        bid: Option<O>,
+        ask: Option<O>,
+    },
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Msg {
     // Order not found on the book.
@@ -41,9 +81,41 @@ pub enum Msg {
     StpCancelBoth,
     /// STP CancelMaker: resting maker(s) cancelled (same-owner); taker may still fill against others.
     StpCancelMaker,
+    /// STP DecrementAndCancel: the smaller of taker and same-owner maker cancelled, the larger
+    /// reduced by its quantity; no trade.
+    StpDecrementAndCancel,
+    /// Market order had no match (nothing to do).
+    MarketNoFill,
+    /// Market order had leftover quantity after sweeping the book.
+    MarketLeftover,
+    /// Pegged order had no reference price to peg to.
+    PegNoReference,
+    /// GTD/GTT order reached its expiry time.
+    Expired,
+    /// DAY order purged at the end of the trading session.
+    SessionEnd,
+    /// Order could not fill its minimum quantity on entry.
+    MinQtyNotFilled,
+    /// Order reached the book's price collar; remainder cancelled.
+    PriceCollar,
+    /// Order would have traded outside the book's price band; remainder cancelled.
+    PriceBand,
+    /// Book is halted; the op was refused.
+    Halted,
+    /// Book is closed; the op was refused.
+    Closed,
+    /// Quote refused as a whole: a side's order is on the wrong side, for another owner,
+    /// not a resting limit (IOC/FOK, market, stop, minimum quantity), expired or held by
+    /// the speed bump; the bid crosses the ask or shares its id; or a new order id is
+    /// already in use.
+    QuoteRejected,
+    /// IOC/FOK order refused in a call phase (see `Phase`) or batch auction.
+    NotInAuction,
+    /// Replace refused because it would take liquidity under a speed bump; the order stands.
+    SpeedBump,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instruction<O: OrderInterface> {
     /// (Order, Remaining Quantity)
     Insert(O, O::N),
@@ -51,20 +123,195 @@ pub enum Instruction<O: OrderInterface> {
     Delete(O::I, Msg),
     /// (Order ID, Owner ID, Price, Quantity, IsTaker)
     Fill(O::I, O::O, O::N, O::N, bool),
+    /// (Order ID, New Price, New Quantity, Remaining Quantity)
+    /// Amends a resting order. Same price with no remaining increase is done in place;
+    /// otherwise the order is re-queued at the back of its new level (or removed if
+    /// remaining is zero).
+    Replace(O::I, O::N, O::N, O::N),
+    /// (Order ID, Reduce By)
+    /// Reduces a resting order's remaining quantity in place, keeping queue position.
+    Reduce(O::I, O::N),
+    /// (Order)
+    /// Holds an untriggered stop order off the book.
+    Hold(O),
+    /// (Phase)
+    /// Moves the book to a new trading phase.
+    Phase(Phase),
+    /// (Order)
+    /// Holds a market order off the book for the call auction's uncross.
+    Auction(O),
+    /// (Order, Release Time)
+    /// Holds a liquidity-taking order off the book until the speed bump releases it.
+    Delay(O, u64),
+    /// (Owner ID, Bid ID, Ask ID)
+    /// Records the owner's current quotes.
+    Quote(O::O, Option<O::I>, Option<O::I>),
     /// (Reason)
     NoOp(O::I, Msg),
 }
 
+/// (maker_id, maker_owner, maker_price, fill_qty, maker_avail, iceberg_visible) — avail
+/// cached to skip re-hashing in temp update; visible is the displayed slice after the fill.
+type MakerFill<O> = (
+    <O as OrderInterface>::I,
+    <O as OrderInterface>::O,
+    <O as OrderInterface>::N,
+    <O as OrderInterface>::N,
+    <O as OrderInterface>::N,
+    Option<<O as OrderInterface>::N>,
+);
+
+/// (maker, filled, iceberg_visible) for a lead maker filled from its entitlement.
+type LeadFill<O> = (
+    *const O,
+    <O as OrderInterface>::N,
+    Option<<O as OrderInterface>::N>,
+);
+
+/// (maker, avail, slice) for an iceberg whose slice was refreshed at the current level.
+type Refill<O> = (*const O, <O as OrderInterface>::N, <O as OrderInterface>::N);
+
+/// (maker, avail, shown) for a maker eligible for a pro-rata split at the current level.
+type Share<O> = (*const O, <O as OrderInterface>::N, <O as OrderInterface>::N);
+
+/// Result of sweeping the opposite side for a taker.
+enum Sweep<N> {
+    /// (Remaining, Total Filled, Price * Quantity, Stopped At Bound)
+    Done(N, N, N, bool),
+    /// Taker rejected; any makers left in `stp_cancels` are deleted with the same reason.
+    Rejected(Msg),
+    /// Post-only taker would trade at this price.
+    Crossed(N),
+}
+
+/// Returns the quantity `maker` shows at `level` out of `avail`: its displayed slice
+/// (as left by earlier ops in the batch) for icebergs, all of it otherwise.
+#[inline(always)]
+fn shown<O: OrderInterface>(
+    level: &Level<O>,
+    visible: &FxHashMap<O::I, O::N>,
+    maker: &O,
+    avail: O::N,
+) -> O::N {
+    match maker.display_quantity() {
+        Some(_) => visible
+            .get(maker.id())
+            .copied()
+            .unwrap_or_else(|| level.visible(maker))
+            .min(avail),
+        None => avail,
+    }
+}
+
+/// Returns true if `maker` trades a fill of `fill` out of `avail`: all-or-none makers only
+/// in full, makers with a resting minimum only at or above it.
+#[inline(always)]
+fn accepts<O: OrderInterface>(maker: &O, fill: O::N, avail: O::N) -> bool {
+    if maker.all_or_none() && fill < avail {
+        return false;
+    }
+    match maker.min_qty() {
+        Some(min) if min.resting => min.accepts(fill, avail),
+        _ => true,
+    }
+}
+
+/// Returns true if `price` is strictly better than `limit` for a buy (or sell), or there
+/// is no limit.
+#[inline(always)]
+fn inside<N: Ord>(is_buy: bool, price: N, limit: Option<N>) -> bool {
+    limit.is_none_or(|limit| (is_buy && price < limit) || (!is_buy && price > limit))
+}
+
+/// Returns the collar price for `order` if tighter than its `limit`; post-only orders
+/// never sweep.
+#[inline(always)]
+fn collar<O: OrderInterface>(ob: &OrderBook<O>, order: &O, limit: Option<O::N>) -> Option<O::N> {
+    let is_buy = order.is_buy();
+    ob.collar
+        .filter(|_| !order.post_only())
+        .and_then(|collar| {
+            let best = if is_buy {
+                ob.asks.best()
+            } else {
+                ob.bids.best()
+            };
+            collar.price(is_buy, best.map(|(price, _)| price), ob.last_trade)
+        })
+        .filter(|&collar| inside(is_buy, collar, limit))
+}
+
+/// Returns the tighter of the `collar` and `band` prices for a buy (or sell).
+#[inline(always)]
+fn bound<N: Ord + Copy>(is_buy: bool, collar: Option<N>, band: Option<N>) -> Option<N> {
+    match (collar, band) {
+        (Some(collar), Some(band)) if inside(is_buy, collar, Some(band)) => Some(collar),
+        (collar, None) => collar,
+        (_, band) => band,
+    }
+}
+
+/// Returns the band price for `order` if tighter than its `limit`; post-only orders never
+/// sweep.
+#[inline(always)]
+fn band<O: OrderInterface>(ob: &OrderBook<O>, order: &O, limit: Option<O::N>) -> Option<O::N> {
+    let is_buy = order.is_buy();
+    ob.band
+        .filter(|_| !order.post_only())
+        .and_then(|band| band.price(is_buy, ob.last_trade))
+        .filter(|&band| inside(is_buy, band, limit))
+}
+
+/// Returns true if `order` would take liquidity on arrival against the book as applied:
+/// a market order, or a limit crossing the best opposite price. Post-only and pegged
+/// orders never do.
+#[inline(always)]
+fn takes<O: OrderInterface>(ob: &OrderBook<O>, order: &O) -> bool {
+    if order.post_only() || order.peg().is_some() {
+        return false;
+    }
+    if order.order_type() != OrderType::Limit {
+        return true;
+    }
+    crosses(ob, order.is_buy(), order.price())
+}
+
+/// Returns true if a buy (or sell) at `price` crosses the best opposite price as applied.
+#[inline(always)]
+fn crosses<O: OrderInterface>(ob: &OrderBook<O>, is_buy: bool, price: O::N) -> bool {
+    let opposite = if is_buy { &ob.asks } else { &ob.bids };
+    opposite.iter().next().is_some_and(|level| {
+        (is_buy && level.price() <= price) || (!is_buy && level.price() >= price)
+    })
+}
+
 /// Evaluator: turns ops into instructions without mutating the book.
 ///
 /// Reusable — call `reset()` between independent batches. Across calls between `reset()`s,
-/// `temp` tracks virtual remaining qty so later ops see earlier effects. `fills`,
-/// `stp_cancels`, and `out` are kept as struct fields to avoid per-call heap allocation.
+/// `temp` tracks virtual remaining qty so later ops see earlier effects, and `visible`
+/// the displayed slice of touched icebergs. `fills`, `stp_cancels`, `out` and the sweep's
+/// scratch buffers are kept as struct fields to avoid per-call heap allocation.
 pub struct Evaluator<O: OrderInterface> {
     temp: FxHashMap<O::I, O::N>,
-    // (maker_id, maker_owner, maker_price, fill_qty, maker_avail) — avail cached to skip re-hashing in temp update
-    fills: Vec<(O::I, O::O, O::N, O::N, O::N)>,
+    visible: FxHashMap<O::I, O::N>,
+    fills: Vec<MakerFill<O>>,
     stp_cancels: Vec<O::I>,
+    /// (Maker ID, Reduce By, Remaining After) for an STP decrement of a larger same-owner
+    /// maker.
+    stp_reduce: Option<(O::I, O::N, O::N)>,
+    /// Prices of levels past a taker's limit with a maker whose discretion reaches it.
+    reach: Vec<O::N>,
+    // Sweep scratch, per level. Makers point into the book being swept; cleared before
+    // use and only read during that sweep.
+    refills: VecDeque<Refill<O>>,
+    shares: Vec<Share<O>>,
+    sizes: Vec<O::N>,
+    allocs: Vec<O::N>,
+    lead: Vec<LeadFill<O>>,
+    /// Phase set by an earlier op in the batch, not yet applied.
+    phase: Option<Phase>,
+    /// Instructions of a quote's first side while the second is evaluated.
+    staged: Vec<Instruction<O>>,
     out: Vec<Instruction<O>>,
 }
 
@@ -72,8 +319,18 @@ impl<O: OrderInterface> Default for Evaluator<O> {
     fn default() -> Self {
         Self {
             temp: FxHashMap::default(),
+            visible: FxHashMap::default(),
             fills: Vec::new(),
             stp_cancels: Vec::new(),
+            stp_reduce: None,
+            reach: Vec::new(),
+            refills: VecDeque::new(),
+            shares: Vec::new(),
+            sizes: Vec::new(),
+            allocs: Vec::new(),
+            lead: Vec::new(),
+            phase: None,
+            staged: Vec::new(),
             out: Vec::new(),
         }
     }
@@ -84,162 +341,1074 @@ impl<O: OrderInterface> Evaluator<O> {
     #[inline]
     pub fn reset(&mut self) {
         self.temp.clear();
+        self.visible.clear();
+        self.phase = None;
     }
 
     /// Evaluates a single op; returns a draining iterator of instructions.
     /// Does not mutate `ob`. The returned `Drain` yields owned `Instruction` values
     /// while preserving the internal buffer for reuse.
+    ///
+    /// The phase is the book's, or the one set by an earlier op in the batch. While
+    /// halted or closed, inserts, replaces and triggered stops return `NoOp` with
+    /// `Msg::Halted` or `Msg::Closed`.
     #[inline]
     pub fn eval(&mut self, ob: &OrderBook<O>, op: Op<O>) -> std::vec::Drain<'_, Instruction<O>> {
+        let refused = match self.phase(ob) {
+            Phase::Halted => Some(Msg::Halted),
+            Phase::Closed => Some(Msg::Closed),
+            _ => None,
+        };
+        if let Some(msg) = refused {
+            let id = match &op {
+                Op::Insert(order) | Op::Trigger(order) => Some(order.id()),
+                Op::Replace { id, .. } => Some(id),
+                Op::Quote { bid, ask, .. } => bid.as_ref().or(ask.as_ref()).map(|order| order.id()),
+                Op::Delete(_) | Op::Reduce { .. } | Op::Phase(_) | Op::Uncross => None,
+            };
+            if let Some(id) = id {
+                self.out.clear();
+                self.out.push(Instruction::NoOp(id.clone(), msg));
+                return self.out.drain(..);
+            }
+        }
         match op {
             Op::Insert(order) => self.eval_insert(ob, order),
             Op::Delete(order_id) => self.eval_cancel(ob, order_id),
+            Op::Replace {
+                id,
+                new_price,
+                new_quantity,
+            } => self.eval_replace(ob, id, new_price, new_quantity),
+            Op::Reduce { id, quantity } => self.eval_reduce(ob, id, quantity),
+            Op::Trigger(order) => self.eval_trigger(ob, order),
+            Op::Phase(phase) => self.eval_phase(ob, phase),
+            Op::Uncross => self.eval_uncross(ob),
+            Op::Quote { owner, bid, ask } => self.eval_quote(ob, owner, bid, ask),
         }
     }
 
-    /// Evaluates a single insert operation.
+    /// Returns the release time if the speed bump holds `order` on entry: it would take
+    /// liquidity in the continuous phase without batch auctions.
     #[inline(always)]
-    pub fn eval_insert(
+    fn delay(&self, ob: &OrderBook<O>, order: &O) -> Option<u64> {
+        let delay = ob.speed_bump?;
+        let now = ob.now()?;
+        (self.phase(ob) == Phase::Continuous && !ob.collects(Phase::Continuous) && takes(ob, order))
+            .then_some(now + delay)
+    }
+
+    /// Returns the phase ops are evaluated in.
+    #[inline(always)]
+    fn phase(&self, ob: &OrderBook<O>) -> Phase {
+        self.phase.unwrap_or(ob.phase)
+    }
+
+    /// Evaluates a quote: the cancels and in-place amends of the owner's current quotes,
+    /// then the new or moved bid and ask, then the record of the owner's new quotes.
+    /// Quotes are read as applied, so a later quote in the same batch does not see this
+    /// one.
+    pub fn eval_quote(
         &mut self,
         ob: &OrderBook<O>,
-        order: O,
+        owner: O::O,
+        bid: Option<O>,
+        ask: Option<O>,
     ) -> std::vec::Drain<'_, Instruction<O>> {
-        if ob.orders.contains_key(order.id()) {
+        let zero = O::N::default();
+        let (old_bid, old_ask) = ob.quotes.get(&owner).cloned().unwrap_or_default();
+        let live = |id: Option<O::I>, temp: &FxHashMap<O::I, O::N>| {
+            id.filter(|id| ob.contains(id) && temp.get(id) != Some(&zero))
+        };
+        let old_bid = live(old_bid, &self.temp);
+        let old_ask = live(old_ask, &self.temp);
+
+        // Sides that could be refused on their own are refused with the whole quote.
+        let now = ob.now();
+        let valid = |order: &Option<O>, is_buy: bool, old: &Option<O::I>| {
+            order.as_ref().is_none_or(|order| {
+                order.is_buy() == is_buy
+                    && *order.owner() == owner
+                    && order.order_type() == OrderType::Limit
+                    && !matches!(order.tif(), TIF::IOC | TIF::FOK)
+                    && order.stop_price().is_none()
+                    && order.trail().is_none()
+                    && order.min_qty().is_none()
+                    && now.is_none_or(|now| Expiries::expiry(order).is_none_or(|at| at > now))
+                    && self.delay(ob, order).is_none()
+                    && if old.as_ref() == Some(order.id()) {
+                        ob.orders.contains_key(order.id())
+                    } else {
+                        !ob.contains(order.id())
+                    }
+            })
+        };
+        let conflict = match (&bid, &ask) {
+            (Some(bid), Some(ask)) => bid.price() >= ask.price() || bid.id() == ask.id(),
+            _ => false,
+        };
+        if conflict || !valid(&bid, true, &old_bid) || !valid(&ask, false, &old_ask) {
             self.out.clear();
-            self.out.push(Instruction::NoOp(
-                order.id().clone(),
-                Msg::OrderAlreadyExists,
+            if let Some(order) = bid.as_ref().or(ask.as_ref()) {
+                self.out
+                    .push(Instruction::NoOp(order.id().clone(), Msg::QuoteRejected));
+            }
+            return self.out.drain(..);
+        }
+
+        // Old quotes first: cancels and in-place amends, so neither new side trades with
+        // the quote it replaces. A side keeping its id that moves is hidden until its
+        // replace is evaluated; its saved `temp` entry is put back then.
+        let mut staged = std::mem::take(&mut self.staged);
+        staged.clear();
+        let mut kept = [false; 2];
+        let mut moved = [None, None];
+        for (side, (old, new)) in [(old_bid, &bid), (old_ask, &ask)].into_iter().enumerate() {
+            let Some(id) = old else {
+                continue;
+            };
+            match new {
+                Some(order) if *order.id() == id => {
+                    let resting = ob.order(&id).unwrap();
+                    let (price, quantity) = (order.price(), order.quantity());
+                    if price == resting.price() && quantity <= resting.quantity() {
+                        staged.extend(self.eval_replace(ob, id, price, quantity));
+                        kept[side] = true;
+                    } else {
+                        moved[side] = Some(self.temp.insert(id, zero));
+                    }
+                }
+                _ => staged.extend(self.eval_cancel(ob, id)),
+            }
+        }
+
+        // Then the new sides. A side refused on entry is left out of the record.
+        let mut ids = [None, None];
+        for (side, new) in [bid, ask].into_iter().enumerate() {
+            let Some(order) = new else {
+                continue;
+            };
+            let id = order.id().clone();
+            if kept[side] {
+                ids[side] = Some(id);
+            } else if let Some(saved) = moved[side].take() {
+                match saved {
+                    Some(avail) => self.temp.insert(id.clone(), avail),
+                    None => self.temp.remove(&id),
+                };
+                let (price, quantity) = (order.price(), order.quantity());
+                staged.extend(self.eval_replace(ob, id.clone(), price, quantity));
+                ids[side] = Some(id);
+            } else {
+                staged.extend(self.eval_insert(ob, order));
+                if !matches!(staged.last(), Some(Instruction::NoOp(noop, _)) if *noop == id) {
+                    ids[side] = Some(id);
+                }
+            }
+        }
+        let [bid_id, ask_id] = ids;
+        self.out.clear();
+        self.out.append(&mut staged);
+        self.staged = staged;
+        self.out.push(Instruction::Quote(owner, bid_id, ask_id));
+        self.out.drain(..)
+    }
+
+    /// Evaluates an uncross, reading the book as applied. No instructions unless orders
+    /// are being collected.
+    #[inline]
+    pub fn eval_uncross(&mut self, ob: &OrderBook<O>) -> std::vec::Drain<'_, Instruction<O>> {
+        self.out.clear();
+        if ob.collects(self.phase(ob)) {
+            self.out.extend(auction::uncross(
+                &ob.bids,
+                &ob.asks,
+                &ob.auction,
+                ob.last_trade,
             ));
+        }
+        self.out.drain(..)
+    }
+
+    /// Evaluates a phase change. Leaving a call phase for any other phase uncrosses the
+    /// book first (see `OrderBook::uncross`), reading it as applied. No instructions if
+    /// the phase is unchanged.
+    #[inline]
+    pub fn eval_phase(
+        &mut self,
+        ob: &OrderBook<O>,
+        phase: Phase,
+    ) -> std::vec::Drain<'_, Instruction<O>> {
+        self.out.clear();
+        let current = self.phase(ob);
+        if phase == current {
             return self.out.drain(..);
         }
+        if current.is_call() && !phase.is_call() {
+            self.out.extend(auction::uncross(
+                &ob.bids,
+                &ob.asks,
+                &ob.auction,
+                ob.last_trade,
+            ));
+        }
+        self.phase = Some(phase);
+        self.out.push(Instruction::Phase(phase));
+        self.out.drain(..)
+    }
 
-        let tif = order.tif();
-        let post_only = order.post_only();
-        let stp = order.stp();
-        let taker_owner = order.owner();
-        let is_buy = order.is_buy();
-        let price = order.price();
+    /// Matches `taker` up to limit `price` (unlimited if `None`) for `remaining` against
+    /// the opposite side, stopping before any level past `bound` (collar or band).
+    /// Fills are left in `fills` and STP maker cancels in `stp_cancels` (reductions in
+    /// `stp_reduce`); `temp` is not updated until the caller commits them.
+    ///
+    /// Icebergs match their displayed slice in queue order; a refreshed slice queues behind
+    /// the rest of the level. Queue moves across ops in a batch take effect when applied.
+    /// An all-or-none taker that cannot fill completely gets no fills.
+    ///
+    /// Once past the limit, makers with a discretionary price reaching it still match,
+    /// level by level, at the limit price (not for post-only takers).
+    ///
+    /// Hidden orders match after every displayed order at their level, including refreshed
+    /// slices.
+    ///
+    /// Under pro-rata allocation every eligible maker at a level shares the fill instead,
+    /// displayed orders before hidden ones, each iceberg up to its displayed slice;
+    /// refreshed slices wait for the next taker.
+    /// With lead market makers set, their entitlement at the first level is filled before
+    /// either.
+    #[inline(always)]
+    fn sweep(
+        &mut self,
+        ob: &OrderBook<O>,
+        taker: &O,
+        price: Option<O::N>,
+        bound: Option<O::N>,
+        mut remaining: O::N,
+    ) -> Sweep<O::N> {
+        let quantity = remaining;
+        let post_only = taker.post_only();
+        let stp = taker.stp();
+        let scope = taker.stp_scope();
+        let taker_key = taker.stp_keys().get(scope);
+        let self_trade =
+            |maker: &O| taker_key.is_some() && maker.stp_keys().get(scope) == taker_key;
+        let is_buy = taker.is_buy();
+        let reaches = |maker: &O, limit: O::N| {
+            maker
+                .discretion_price()
+                .is_some_and(|reach| (is_buy && reach <= limit) || (!is_buy && reach >= limit))
+        };
         let opposite = if is_buy { &ob.asks } else { &ob.bids };
 
         let zero = O::N::default();
-        let mut remaining = order.remaining();
         let mut total_filled = zero;
         let mut weighted_price = zero;
+        let mut stopped = false;
 
         let Evaluator {
             temp,
+            visible,
             fills,
             stp_cancels,
+            stp_reduce,
+            reach,
+            refills,
+            shares,
+            sizes,
+            allocs,
+            lead,
             out,
+            ..
         } = self;
         out.clear();
         fills.clear();
         stp_cancels.clear();
+        *stp_reduce = None;
+        shares.clear();
+
+        // Levels up to the limit, then only levels past it holding a maker whose
+        // discretion reaches it; those trade at the limit price.
+        let past = |limit: Option<O::N>, level: &Level<O>| {
+            limit.filter(|&limit| {
+                (is_buy && limit < level.price()) || (!is_buy && limit > level.price())
+            })
+        };
+        reach.clear();
+        if !post_only
+            && opposite.has_discretionary()
+            && let Some(limit) = price
+        {
+            opposite.reaching(limit, reach);
+        }
+        let levels = opposite
+            .iter()
+            .take_while(|level| past(price, level).is_none())
+            .chain(reach.iter().filter_map(|&price| opposite.level(price)));
 
-        'outer: for level in opposite.iter() {
-            if (is_buy && price < level.price()) || (!is_buy && price > level.price()) {
+        'outer: for (depth, level) in levels.enumerate() {
+            let discretion = past(price, level);
+            if bound.is_some_and(|bound| {
+                (is_buy && level.price() > bound) || (!is_buy && level.price() < bound)
+            }) {
+                stopped = true;
                 break;
             }
-            let level_price = level.price();
-            for maker in level.iter() {
-                if remaining == zero {
-                    break 'outer;
-                }
-                let maker_avail = *temp.get(maker.id()).unwrap_or(&maker.remaining());
-                if maker_avail == zero {
-                    continue;
-                }
-                let fill_qty = remaining.min(maker_avail);
+            let fill_price = discretion.unwrap_or(level.price());
+            refills.clear();
+            lead.clear();
 
-                if post_only {
-                    out.push(Instruction::NoOp(order.id().clone(), Msg::PostOnlyFilled));
-                    return out.drain(..);
+            // Lead market maker entitlement, displayed orders in queue order. Self-trading
+            // makers are left to STP in the passes below.
+            if depth == 0
+                && discretion.is_none()
+                && let Some(leads) = &ob.lead_makers
+            {
+                let (num, den) = leads.share;
+                let mut entitled = remaining * num / den;
+                for maker in level.iter_displayed() {
+                    if entitled == zero {
+                        break;
+                    }
+                    if !leads.owners.contains(maker.owner())
+                        || (stp != STP::None && self_trade(maker))
+                    {
+                        continue;
+                    }
+                    let maker_avail = *temp.get(maker.id()).unwrap_or(&maker.remaining());
+                    if maker_avail == zero {
+                        continue;
+                    }
+                    let shown = shown(level, visible, maker, maker_avail);
+                    let fill_qty = entitled.min(shown);
+                    if !accepts(maker, fill_qty, maker_avail) {
+                        continue;
+                    }
+                    if post_only {
+                        return Sweep::Crossed(fill_price);
+                    }
+                    entitled -= fill_qty;
+                    remaining -= fill_qty;
+                    total_filled += fill_qty;
+                    weighted_price += fill_price * fill_qty;
+                    let mut slice = None;
+                    if let Some(display) = maker.display_quantity() {
+                        let left = shown - fill_qty;
+                        if left > zero {
+                            slice = Some(left);
+                        } else {
+                            // Refreshed: the rest of the level goes first.
+                            let next = display.min(maker_avail - fill_qty);
+                            if next > zero {
+                                refills.push_back((maker, maker_avail - fill_qty, next));
+                            }
+                            slice = Some(next);
+                        }
+                    }
+                    fills.push((
+                        maker.id().clone(),
+                        maker.owner().clone(),
+                        fill_price,
+                        fill_qty,
+                        maker_avail,
+                        slice,
+                    ));
+                    lead.push((maker, fill_qty, slice));
                 }
+            }
 
-                if taker_owner == maker.owner() {
-                    match stp {
-                        STP::None => {}
-                        STP::CancelTaker => {
-                            out.push(Instruction::NoOp(order.id().clone(), Msg::StpCancelTaker));
-                            return out.drain(..);
-                        }
-                        STP::CancelMaker => {
-                            stp_cancels.push(maker.id().clone());
-                            temp.insert(maker.id().clone(), zero);
+            // Displayed orders first, then hidden ones.
+            for hidden in [false, true] {
+                let makers = if hidden {
+                    level.iter_hidden()
+                } else {
+                    level.iter_displayed()
+                };
+                for maker in makers {
+                    if remaining == zero {
+                        break 'outer;
+                    }
+                    let mut maker_avail = *temp.get(maker.id()).unwrap_or(&maker.remaining());
+                    let mut shown_after_lead = None;
+                    if !lead.is_empty()
+                        && let Some(&(_, filled, slice)) =
+                            lead.iter().find(|(lead, _, _)| std::ptr::eq(*lead, maker))
+                    {
+                        if refills
+                            .iter()
+                            .any(|(refill, _, _)| std::ptr::eq(*refill, maker))
+                        {
                             continue;
                         }
-                        STP::CancelBoth => {
-                            temp.insert(maker.id().clone(), zero);
-                            out.push(Instruction::NoOp(order.id().clone(), Msg::StpCancelBoth));
-                            out.push(Instruction::Delete(maker.id().clone(), Msg::StpCancelBoth));
-                            return out.drain(..);
+                        maker_avail -= filled;
+                        shown_after_lead = slice;
+                    }
+                    if maker_avail == zero {
+                        continue;
+                    }
+                    if let Some(limit) = discretion
+                        && !reaches(maker, limit)
+                    {
+                        continue;
+                    }
+                    let display = maker.display_quantity();
+                    let shown = shown_after_lead
+                        .unwrap_or_else(|| shown(level, visible, maker, maker_avail));
+                    let fill_qty = remaining.min(shown);
+
+                    // All-or-none or resting minimum not met: skip the maker, it keeps its
+                    // place, keep walking.
+                    if !accepts(maker, fill_qty, maker_avail) {
+                        continue;
+                    }
+
+                    if post_only {
+                        return Sweep::Crossed(fill_price);
+                    }
+
+                    if stp != STP::None && self_trade(maker) {
+                        match stp {
+                            STP::None => {}
+                            STP::CancelTaker => return Sweep::Rejected(Msg::StpCancelTaker),
+                            STP::CancelMaker => {
+                                stp_cancels.push(maker.id().clone());
+                                continue;
+                            }
+                            STP::CancelBoth => {
+                                stp_cancels.clear();
+                                stp_cancels.push(maker.id().clone());
+                                return Sweep::Rejected(Msg::StpCancelBoth);
+                            }
+                            STP::DecrementAndCancel if maker_avail <= remaining => {
+                                stp_cancels.push(maker.id().clone());
+                                remaining -= maker_avail;
+                                continue;
+                            }
+                            STP::DecrementAndCancel => {
+                                *stp_reduce =
+                                    Some((maker.id().clone(), remaining, maker_avail - remaining));
+                                remaining = zero;
+                                break 'outer;
+                            }
                         }
                     }
+
+                    if let Allocation::ProRata { .. } = ob.allocation {
+                        shares.push((maker, maker_avail, shown));
+                        continue;
+                    }
+
+                    remaining -= fill_qty;
+                    total_filled += fill_qty;
+                    weighted_price += fill_price * fill_qty;
+                    let slice = display.map(|display| {
+                        let left = shown - fill_qty;
+                        if left > zero {
+                            return left;
+                        }
+                        let next = display.min(maker_avail - fill_qty);
+                        if next > zero {
+                            refills.push_back((maker, maker_avail - fill_qty, next));
+                        }
+                        next
+                    });
+                    fills.push((
+                        maker.id().clone(),
+                        maker.owner().clone(),
+                        fill_price,
+                        fill_qty,
+                        maker_avail,
+                        slice,
+                    ));
                 }
 
-                remaining -= fill_qty;
-                total_filled += fill_qty;
-                weighted_price += level_price * fill_qty;
-                fills.push((
-                    maker.id().clone(),
-                    maker.owner().clone(),
-                    level_price,
-                    fill_qty,
-                    maker_avail,
-                ));
+                if let Allocation::ProRata { top_order } = ob.allocation {
+                    // Makers allocated less than they accept drop out and the split is redone.
+                    loop {
+                        sizes.clear();
+                        sizes.extend(shares.iter().map(|&(_, _, shown)| shown));
+                        pro_rata::<O>(remaining, sizes, top_order && depth == 0 && !hidden, allocs);
+                        let Some(pos) = shares.iter().zip(allocs.iter()).position(
+                            |(&(maker, maker_avail, _), &fill_qty)| {
+                                let maker = unsafe { &*maker };
+                                fill_qty > zero && !accepts(maker, fill_qty, maker_avail)
+                            },
+                        ) else {
+                            break;
+                        };
+                        shares.remove(pos);
+                    }
+                    for (&(maker, maker_avail, shown), &fill_qty) in
+                        shares.iter().zip(allocs.iter())
+                    {
+                        if fill_qty == zero {
+                            continue;
+                        }
+                        let maker = unsafe { &*maker };
+                        remaining -= fill_qty;
+                        total_filled += fill_qty;
+                        weighted_price += fill_price * fill_qty;
+                        let slice = maker.display_quantity().map(|display| {
+                            let left = shown - fill_qty;
+                            if left > zero {
+                                left
+                            } else {
+                                display.min(maker_avail - fill_qty)
+                            }
+                        });
+                        fills.push((
+                            maker.id().clone(),
+                            maker.owner().clone(),
+                            fill_price,
+                            fill_qty,
+                            maker_avail,
+                            slice,
+                        ));
+                    }
+                    shares.clear();
+                    if remaining == zero {
+                        break 'outer;
+                    }
+                    continue;
+                }
+
+                // Refreshed iceberg slices, behind the other displayed orders at this level.
+                while remaining > zero
+                    && let Some((maker, maker_avail, shown)) = refills.pop_front()
+                {
+                    let maker = unsafe { &*maker };
+                    let fill_qty = remaining.min(shown);
+                    if !accepts(maker, fill_qty, maker_avail) {
+                        continue;
+                    }
+                    remaining -= fill_qty;
+                    total_filled += fill_qty;
+                    weighted_price += fill_price * fill_qty;
+                    let mut slice = shown - fill_qty;
+                    if slice == zero {
+                        slice = maker
+                            .display_quantity()
+                            .unwrap()
+                            .min(maker_avail - fill_qty);
+                        if slice > zero {
+                            refills.push_back((maker, maker_avail - fill_qty, slice));
+                        }
+                    }
+                    fills.push((
+                        maker.id().clone(),
+                        maker.owner().clone(),
+                        fill_price,
+                        fill_qty,
+                        maker_avail,
+                        Some(slice),
+                    ));
+                }
             }
         }
 
-        if tif == TIF::FOK && remaining > zero {
-            out.push(Instruction::NoOp(order.id().clone(), Msg::FOKNotFilled));
-            return out.drain(..);
+        // All-or-none taker that cannot fill completely does not trade at all.
+        if taker.all_or_none() && remaining > zero {
+            fills.clear();
+            stp_cancels.clear();
+            *stp_reduce = None;
+            return Sweep::Done(quantity, zero, zero, stopped);
         }
 
-        for &(ref id, _, _, qty, avail) in fills.iter() {
+        Sweep::Done(remaining, total_filled, weighted_price, stopped)
+    }
+
+    /// Commits `fills` and STP maker cancels and reductions to `temp` and emits the taker
+    /// fill (average price) followed by maker fills and the STP cancels and reductions.
+    #[inline(always)]
+    fn emit_fills(&mut self, taker: &O, total_filled: O::N, weighted_price: O::N) {
+        let Evaluator {
+            temp,
+            visible,
+            fills,
+            stp_cancels,
+            stp_reduce,
+            out,
+            ..
+        } = self;
+        for &(ref id, _, _, qty, avail, slice) in fills.iter() {
             temp.insert(id.clone(), avail - qty);
+            if let Some(slice) = slice {
+                visible.insert(id.clone(), slice);
+            }
+        }
+        if total_filled > O::N::default() {
+            let avg_price = weighted_price / total_filled;
+            out.push(Instruction::Fill(
+                taker.id().clone(),
+                taker.owner().clone(),
+                avg_price,
+                total_filled,
+                true,
+            ));
         }
+        out.extend(
+            fills.drain(..).map(|(id, owner, price, qty, _, _)| {
+                Instruction::Fill(id, owner, price, qty, false)
+            }),
+        );
+        let msg = match taker.stp() {
+            STP::DecrementAndCancel => Msg::StpDecrementAndCancel,
+            _ => Msg::StpCancelMaker,
+        };
+        for id in stp_cancels.drain(..) {
+            temp.insert(id.clone(), O::N::default());
+            out.push(Instruction::Delete(id, msg));
+        }
+        if let Some((id, quantity, avail)) = stp_reduce.take() {
+            temp.insert(id.clone(), avail);
+            out.push(Instruction::Reduce(id, quantity));
+        }
+    }
 
-        let has_activity = !fills.is_empty() || !stp_cancels.is_empty();
+    /// Commits the makers left in `stp_cancels` by a rejected taker to `temp` and emits
+    /// their deletes with the taker's reason.
+    #[inline(always)]
+    fn emit_stp_rejected(&mut self, msg: Msg) {
+        let Evaluator {
+            temp,
+            stp_cancels,
+            out,
+            ..
+        } = self;
+        for id in stp_cancels.drain(..) {
+            temp.insert(id.clone(), O::N::default());
+            out.push(Instruction::Delete(id, msg));
+        }
+    }
 
+    /// Evaluates a single insert operation.
+    #[inline(always)]
+    pub fn eval_insert(
+        &mut self,
+        ob: &OrderBook<O>,
+        order: O,
+    ) -> std::vec::Drain<'_, Instruction<O>> {
+        if ob.contains(order.id()) {
+            self.out.clear();
+            self.out.push(Instruction::NoOp(
+                order.id().clone(),
+                Msg::OrderAlreadyExists,
+            ));
+            return self.out.drain(..);
+        }
+
+        if let Some(now) = ob.now()
+            && Expiries::expiry(&order).is_some_and(|at| at <= now)
+        {
+            self.out.clear();
+            self.out
+                .push(Instruction::NoOp(order.id().clone(), Msg::Expired));
+            return self.out.drain(..);
+        }
+
+        if order.trail().is_some() {
+            self.out.clear();
+            self.out.push(Instruction::Hold(order));
+            return self.out.drain(..);
+        }
+
+        if let Some(stop) = order.stop_price() {
+            let triggered = match ob.last_trade {
+                Some(last) => (order.is_buy() && last >= stop) || (!order.is_buy() && last <= stop),
+                None => false,
+            };
+            if !triggered {
+                self.out.clear();
+                self.out.push(Instruction::Hold(order));
+                return self.out.drain(..);
+            }
+        }
+
+        if let Some(release) = self.delay(ob, &order) {
+            self.out.clear();
+            self.out.push(Instruction::Delay(order, release));
+            return self.out.drain(..);
+        }
+
+        self.eval_active(ob, order)
+    }
+
+    /// Evaluates an order released by the book (see `Op::Trigger`). Rejected if its id is
+    /// already in use or it expired while held.
+    #[inline(always)]
+    pub fn eval_trigger(
+        &mut self,
+        ob: &OrderBook<O>,
+        order: O,
+    ) -> std::vec::Drain<'_, Instruction<O>> {
+        if ob.contains(order.id()) {
+            self.out.clear();
+            self.out.push(Instruction::NoOp(
+                order.id().clone(),
+                Msg::OrderAlreadyExists,
+            ));
+            return self.out.drain(..);
+        }
+        if let Some(now) = ob.now()
+            && Expiries::expiry(&order).is_some_and(|at| at <= now)
+        {
+            self.out.clear();
+            self.out
+                .push(Instruction::NoOp(order.id().clone(), Msg::Expired));
+            return self.out.drain(..);
+        }
+        self.eval_active(ob, order)
+    }
+
+    /// Evaluates an order that is live for matching: a new order or a triggered stop.
+    /// Pegged orders are priced from the best unpegged prices first.
+    #[inline(always)]
+    fn eval_active(
+        &mut self,
+        ob: &OrderBook<O>,
+        mut order: O,
+    ) -> std::vec::Drain<'_, Instruction<O>> {
+        if let Some(peg) = order.peg() {
+            let best_bid = ob.bids.best_unpegged();
+            let best_ask = ob.asks.best_unpegged();
+            let Some(price) = peg.price(order.is_buy(), best_bid, best_ask) else {
+                self.out.clear();
+                self.out
+                    .push(Instruction::NoOp(order.id().clone(), Msg::PegNoReference));
+                return self.out.drain(..);
+            };
+            let quantity = order.quantity();
+            let filled = quantity - order.remaining();
+            order.amend(price, quantity);
+            if filled > O::N::default() {
+                order.fill(filled);
+            }
+        }
+        let tif = order.tif();
+        let order_type = order.order_type();
+        // Call phase or batch: nothing trades until the uncross.
+        if ob.collects(self.phase(ob)) {
+            self.out.clear();
+            let instruction = match (tif, order_type) {
+                (TIF::IOC | TIF::FOK, _) => {
+                    Instruction::NoOp(order.id().clone(), Msg::NotInAuction)
+                }
+                (_, OrderType::Limit) => {
+                    let remaining = order.remaining();
+                    Instruction::Insert(order, remaining)
+                }
+                _ => Instruction::Auction(order),
+            };
+            self.out.push(instruction);
+            return self.out.drain(..);
+        }
+        let zero = O::N::default();
+        let limit = (order_type == OrderType::Limit).then(|| order.price());
+        let collar = collar(ob, &order, limit);
+        let band = band(ob, &order, limit);
+        let bound = bound(order.is_buy(), collar, band);
+        let (remaining, total_filled, weighted_price, stopped) =
+            match self.sweep(ob, &order, limit, bound, order.remaining()) {
+                Sweep::Done(remaining, total_filled, weighted_price, stopped) => {
+                    (remaining, total_filled, weighted_price, stopped)
+                }
+                Sweep::Crossed(price) => {
+                    let Some(price) = ob
+                        .slide(order.is_buy(), price)
+                        .filter(|_| order.post_only_slide())
+                    else {
+                        self.out
+                            .push(Instruction::NoOp(order.id().clone(), Msg::PostOnlyFilled));
+                        return self.out.drain(..);
+                    };
+                    let quantity = order.quantity();
+                    let remaining = order.remaining();
+                    order.amend(price, quantity);
+                    self.out.push(Instruction::Insert(order, remaining));
+                    return self.out.drain(..);
+                }
+                Sweep::Rejected(msg) => {
+                    self.out.push(Instruction::NoOp(order.id().clone(), msg));
+                    self.emit_stp_rejected(msg);
+                    return self.out.drain(..);
+                }
+            };
+
+        if tif == TIF::FOK && remaining > zero {
+            self.out
+                .push(Instruction::NoOp(order.id().clone(), Msg::FOKNotFilled));
+            return self.out.drain(..);
+        }
+
+        if let Some(min) = order.min_qty()
+            && !min.accepts(total_filled, order.remaining())
+        {
+            self.out
+                .push(Instruction::NoOp(order.id().clone(), Msg::MinQtyNotFilled));
+            return self.out.drain(..);
+        }
+
+        let has_activity =
+            !self.fills.is_empty() || !self.stp_cancels.is_empty() || self.stp_reduce.is_some();
+        let last_price = self.fills.last().map(|&(_, _, price, _, _, _)| price);
         if has_activity {
-            let taker_id = order.id().clone();
-            let taker_owner = order.owner().clone();
-
-            if total_filled > zero {
-                let avg_price = weighted_price / total_filled;
-                out.push(Instruction::Fill(
-                    taker_id.clone(),
-                    taker_owner,
-                    avg_price,
-                    total_filled,
-                    true,
+            self.emit_fills(&order, total_filled, weighted_price);
+        }
+        // STP decrement: the taker shrinks by the decremented quantity, or is cancelled
+        // if none is left.
+        let decremented = order.remaining() - remaining - total_filled;
+        if decremented > zero {
+            if remaining == zero {
+                self.out.push(Instruction::Delete(
+                    order.id().clone(),
+                    Msg::StpDecrementAndCancel,
                 ));
+                return self.out.drain(..);
             }
-            out.extend(
-                fills.drain(..).map(|(id, owner, price, qty, _)| {
-                    Instruction::Fill(id, owner, price, qty, false)
-                }),
-            );
-            for id in stp_cancels.drain(..) {
-                out.push(Instruction::Delete(id, Msg::StpCancelMaker));
+            let quantity = order.quantity() - decremented;
+            order.amend(order.price(), quantity);
+        }
+        if remaining == zero {
+            return self.out.drain(..);
+        }
+
+        // Stopped at the band: cancel the remainder, halting if configured.
+        if stopped && band.is_some() && band == bound {
+            if has_activity {
+                self.out
+                    .push(Instruction::Delete(order.id().clone(), Msg::PriceBand));
+            } else {
+                self.out
+                    .push(Instruction::NoOp(order.id().clone(), Msg::PriceBand));
             }
-            if tif == TIF::IOC {
-                if remaining > zero {
-                    out.push(Instruction::Delete(taker_id, Msg::IOCLeftover));
+            self.halt_on_breach(ob);
+            return self.out.drain(..);
+        }
+
+        // Stopped at the collar: rest the remainder there, or cancel it.
+        if stopped && let Some(price) = collar {
+            let rest = ob
+                .collar
+                .is_some_and(|collar| collar.action == CollarAction::Rest);
+            if rest && tif != TIF::IOC {
+                let quantity = order.quantity();
+                order.amend(price, quantity);
+                self.out.push(Instruction::Insert(order, remaining));
+            } else if has_activity {
+                self.out
+                    .push(Instruction::Delete(order.id().clone(), Msg::PriceCollar));
+            } else {
+                self.out
+                    .push(Instruction::NoOp(order.id().clone(), Msg::PriceCollar));
+            }
+            return self.out.drain(..);
+        }
+
+        let (leftover, no_fill) = match (order_type, last_price) {
+            _ if tif == TIF::IOC => (Msg::IOCLeftover, Msg::IOCNoFill),
+            (OrderType::Limit, _) => {
+                self.out.push(Instruction::Insert(order, remaining));
+                return self.out.drain(..);
+            }
+            // Market-to-limit: rest the remainder at the last fill price.
+            (OrderType::MarketToLimit, Some(price)) => {
+                let mut order = order;
+                let quantity = order.quantity();
+                order.amend(price, quantity);
+                self.out.push(Instruction::Insert(order, remaining));
+                return self.out.drain(..);
+            }
+            _ => (Msg::MarketLeftover, Msg::MarketNoFill),
+        };
+        if has_activity {
+            self.out
+                .push(Instruction::Delete(order.id().clone(), leftover));
+        } else {
+            self.out
+                .push(Instruction::NoOp(order.id().clone(), no_fill));
+        }
+        self.out.drain(..)
+    }
+
+    /// Evaluates a single replace operation.
+    ///
+    /// Quantity already filled is kept, so the new remaining is `new_quantity - filled`;
+    /// if that is zero or less the order is cancelled. Like an insert, an order that
+    /// moves is not visible to later ops in the same batch until applied. A marketable
+    /// replace is collared and banded as an insert is, and refused under a speed bump.
+    #[inline(always)]
+    pub fn eval_replace(
+        &mut self,
+        ob: &OrderBook<O>,
+        order_id: O::I,
+        new_price: O::N,
+        new_quantity: O::N,
+    ) -> std::vec::Drain<'_, Instruction<O>> {
+        self.out.clear();
+        let zero = O::N::default();
+        let Some(order) = ob.order(&order_id) else {
+            self.out
+                .push(Instruction::NoOp(order_id, Msg::OrderNotFound));
+            return self.out.drain(..);
+        };
+        let avail = *self.temp.get(&order_id).unwrap_or(&order.remaining());
+        if avail == zero {
+            self.out
+                .push(Instruction::NoOp(order_id, Msg::OrderNotFound));
+            return self.out.drain(..);
+        }
+
+        let filled = order.quantity() - avail;
+        if new_quantity <= filled {
+            self.temp.insert(order_id.clone(), zero);
+            self.out
+                .push(Instruction::Delete(order_id, Msg::UserCancelled));
+            return self.out.drain(..);
+        }
+        let remaining = new_quantity - filled;
+
+        // Pure decrease keeps queue position.
+        if new_price == order.price() && remaining <= avail {
+            self.temp.insert(order_id.clone(), remaining);
+            self.out.push(Instruction::Replace(
+                order_id,
+                new_price,
+                new_quantity,
+                remaining,
+            ));
+            return self.out.drain(..);
+        }
+
+        // Call phase or batch: the order moves without matching.
+        if ob.collects(self.phase(ob)) {
+            self.temp.insert(order_id.clone(), zero);
+            self.out.push(Instruction::Replace(
+                order_id,
+                new_price,
+                new_quantity,
+                remaining,
+            ));
+            return self.out.drain(..);
+        }
+
+        // Speed bump: a replace that would take liquidity is refused.
+        if ob.speed_bump.is_some()
+            && ob.now().is_some()
+            && !order.post_only()
+            && order.peg().is_none()
+            && crosses(ob, order.is_buy(), new_price)
+        {
+            self.out.push(Instruction::NoOp(order_id, Msg::SpeedBump));
+            return self.out.drain(..);
+        }
+
+        let requested = remaining;
+        let collar = collar(ob, order, Some(new_price));
+        let band = band(ob, order, Some(new_price));
+        let bound = bound(order.is_buy(), collar, band);
+        let (remaining, total_filled, weighted_price, stopped) =
+            match self.sweep(ob, order, Some(new_price), bound, requested) {
+                Sweep::Done(remaining, total_filled, weighted_price, stopped) => {
+                    (remaining, total_filled, weighted_price, stopped)
+                }
+                // Post-only: replace rejected, the original order stands.
+                Sweep::Crossed(_) => {
+                    self.out
+                        .push(Instruction::NoOp(order_id, Msg::PostOnlyFilled));
+                    return self.out.drain(..);
+                }
+                Sweep::Rejected(msg) => {
+                    self.temp.insert(order_id.clone(), zero);
+                    self.out.push(Instruction::Delete(order_id, msg));
+                    self.emit_stp_rejected(msg);
+                    return self.out.drain(..);
                 }
-            } else if remaining > zero {
-                out.push(Instruction::Insert(order, remaining));
+            };
+
+        self.emit_fills(order, total_filled, weighted_price);
+        // The amended order is invisible at its old position until applied.
+        self.temp.insert(order_id.clone(), zero);
+        // STP decrement: the order shrinks by the decremented quantity, or is cancelled
+        // if none is left.
+        let decremented = requested - remaining - total_filled;
+        if stopped && remaining > zero {
+            // Stopped at the band: cancel the remainder, halting if configured.
+            if band.is_some() && band == bound {
+                self.out.push(Instruction::Delete(order_id, Msg::PriceBand));
+                self.halt_on_breach(ob);
+                return self.out.drain(..);
             }
-            return out.drain(..);
+            // Stopped at the collar: rest the remainder there, or cancel it.
+            if let Some(price) = collar
+                && ob
+                    .collar
+                    .is_some_and(|collar| collar.action == CollarAction::Rest)
+            {
+                self.out.push(Instruction::Replace(
+                    order_id,
+                    price,
+                    new_quantity - decremented,
+                    remaining,
+                ));
+            } else {
+                self.out
+                    .push(Instruction::Delete(order_id, Msg::PriceCollar));
+            }
+            return self.out.drain(..);
+        }
+        if decremented > zero && remaining == zero {
+            self.out
+                .push(Instruction::Delete(order_id, Msg::StpDecrementAndCancel));
+            return self.out.drain(..);
         }
+        self.out.push(Instruction::Replace(
+            order_id,
+            new_price,
+            new_quantity - decremented,
+            remaining,
+        ));
+        self.out.drain(..)
+    }
 
-        if tif == TIF::IOC {
-            out.push(Instruction::NoOp(order.id().clone(), Msg::IOCNoFill));
-            return out.drain(..);
+    /// Emits a move to `Phase::Halted` after a band breach if the band halts the book.
+    #[inline(always)]
+    fn halt_on_breach(&mut self, ob: &OrderBook<O>) {
+        if ob.band.is_some_and(|band| band.action == BandAction::Halt) {
+            self.phase = Some(Phase::Halted);
+            self.out.push(Instruction::Phase(Phase::Halted));
         }
+    }
 
-        out.push(Instruction::Insert(order, remaining));
-        out.drain(..)
+    /// Evaluates a single reduce operation.
+    #[inline(always)]
+    pub fn eval_reduce(
+        &mut self,
+        ob: &OrderBook<O>,
+        order_id: O::I,
+        quantity: O::N,
+    ) -> std::vec::Drain<'_, Instruction<O>> {
+        self.out.clear();
+        let zero = O::N::default();
+        let Some(order) = ob.order(&order_id) else {
+            self.out
+                .push(Instruction::NoOp(order_id, Msg::OrderNotFound));
+            return self.out.drain(..);
+        };
+        let avail = *self.temp.get(&order_id).unwrap_or(&order.remaining());
+        if avail == zero {
+            self.out
+                .push(Instruction::NoOp(order_id, Msg::OrderNotFound));
+            return self.out.drain(..);
+        }
+        if quantity >= avail {
+            self.temp.insert(order_id.clone(), zero);
+            self.out
+                .push(Instruction::Delete(order_id, Msg::UserCancelled));
+            return self.out.drain(..);
+        }
+        self.temp.insert(order_id.clone(), avail - quantity);
+        self.out.push(Instruction::Reduce(order_id, quantity));
+        self.out.drain(..)
     }
 
     /// Evaluates a single cancel operation.
@@ -250,7 +1419,7 @@ impl<O: OrderInterface> Evaluator<O> {
         order_id: O::I,
     ) -> std::vec::Drain<'_, Instruction<O>> {
         self.out.clear();
-        if !ob.orders.contains_key(&order_id) {
+        if !ob.contains(&order_id) {
             self.out
                 .push(Instruction::NoOp(order_id, Msg::OrderNotFound));
             return self.out.drain(..);
@@ -0,0 +1,182 @@
+use crate::{
+    hash::FxHashMap,
+    order::{OrderInterface, TIF},
+};
+use std::collections::{BTreeMap, VecDeque, btree_map};
+
+/// Index of GTD/GTT orders by expiry timestamp, FIFO within the same timestamp.
+pub struct Expiries<O: OrderInterface> {
+    by_time: BTreeMap<u64, VecDeque<O::I>>,
+    len: usize,
+}
+
+impl<O: OrderInterface> Default for Expiries<O> {
+    fn default() -> Self {
+        Self {
+            by_time: BTreeMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<O: OrderInterface> Expiries<O> {
+    /// Returns the expiry timestamp of `order`, if it has one.
+    #[inline(always)]
+    pub fn expiry(order: &O) -> Option<u64> {
+        if order.tif().expires() {
+            order.expire_at()
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn insert(&mut self, at: u64, order_id: O::I) {
+        self.by_time.entry(at).or_default().push_back(order_id);
+        self.len += 1;
+    }
+
+    #[inline]
+    pub fn remove(&mut self, at: u64, order_id: &O::I) {
+        if let btree_map::Entry::Occupied(mut entry) = self.by_time.entry(at) {
+            let ids = entry.get_mut();
+            if let Some(pos) = ids.iter().position(|id| id == order_id) {
+                ids.remove(pos);
+                self.len -= 1;
+            }
+            if ids.is_empty() {
+                entry.remove();
+            }
+        }
+    }
+
+    /// Returns the ids expiring at or before `now`, earliest first.
+    #[inline]
+    pub fn due(&self, now: u64) -> impl Iterator<Item = &O::I> {
+        self.by_time.range(..=now).flat_map(|(_, ids)| ids.iter())
+    }
+}
+
+/// Index of DAY orders, resting or held, in arrival order.
+pub struct DayOrders<O: OrderInterface> {
+    seq: FxHashMap<O::I, u64>,
+    by_seq: BTreeMap<u64, O::I>,
+    next: u64,
+}
+
+impl<O: OrderInterface> Default for DayOrders<O> {
+    fn default() -> Self {
+        Self {
+            seq: FxHashMap::default(),
+            by_seq: BTreeMap::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<O: OrderInterface> DayOrders<O> {
+    /// Returns true if `order` ends with the session.
+    #[inline(always)]
+    pub fn is_day(order: &O) -> bool {
+        order.tif() == TIF::Day
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.seq.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.seq.is_empty()
+    }
+
+    /// Indexes `order_id` behind the others; no-op if already indexed.
+    #[inline]
+    pub fn insert(&mut self, order_id: O::I) {
+        if self.seq.contains_key(&order_id) {
+            return;
+        }
+        self.seq.insert(order_id.clone(), self.next);
+        self.by_seq.insert(self.next, order_id);
+        self.next += 1;
+    }
+
+    #[inline]
+    pub fn remove(&mut self, order_id: &O::I) {
+        if let Some(seq) = self.seq.remove(order_id) {
+            self.by_seq.remove(&seq);
+        }
+    }
+
+    /// Returns the ids in arrival order.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &O::I> {
+        self.by_seq.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{TIF, TestOrder};
+
+    #[test]
+    fn test_expiry() {
+        let order = TestOrder::new("1", true, 100, 10).with_expire_at(50);
+        assert_eq!(Expiries::expiry(&order), None);
+        assert_eq!(
+            Expiries::expiry(&order.clone().with_tif(TIF::GTD)),
+            Some(50)
+        );
+        assert_eq!(Expiries::expiry(&order.with_tif(TIF::GTT)), Some(50));
+        let order = TestOrder::new("2", true, 100, 10).with_tif(TIF::GTT);
+        assert_eq!(Expiries::expiry(&order), None);
+    }
+
+    #[test]
+    fn test_due_order() {
+        let mut expiries = Expiries::<TestOrder>::default();
+        assert!(expiries.is_empty());
+        expiries.insert(30, String::from("c"));
+        expiries.insert(10, String::from("a"));
+        expiries.insert(10, String::from("b"));
+        expiries.insert(20, String::from("x"));
+        expiries.remove(20, &String::from("x"));
+        expiries.remove(20, &String::from("x"));
+        expiries.remove(10, &String::from("z"));
+        assert_eq!(expiries.len(), 3);
+
+        let due: Vec<_> = expiries.due(9).collect();
+        assert!(due.is_empty());
+        let due: Vec<_> = expiries.due(30).collect();
+        assert_eq!(due, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_day_orders_arrival_order() {
+        let mut days = DayOrders::<TestOrder>::default();
+        assert!(days.is_empty());
+        days.insert(String::from("b"));
+        days.insert(String::from("a"));
+        days.insert(String::from("b"));
+        days.insert(String::from("x"));
+        days.remove(&String::from("x"));
+        days.remove(&String::from("x"));
+        days.insert(String::from("c"));
+        assert_eq!(days.len(), 3);
+
+        let ids: Vec<_> = days.iter().collect();
+        assert_eq!(ids, vec!["b", "a", "c"]);
+    }
+}
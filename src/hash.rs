@@ -331,7 +331,7 @@ mod tests {
 
     #[test]
     fn test_fx_build_hasher() {
-        let builder = FxBuildHasher::default();
+        let builder = FxBuildHasher;
         let hasher = builder.build_hasher();
         assert_eq!(hasher.hash, 0);
     }
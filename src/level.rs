@@ -1,14 +1,23 @@
 use crate::{
+    hash::FxHashMap,
     list::{Iter, IterMut, List, Node, Pool},
     order::OrderInterface,
 };
+use std::iter::Chain;
 
 /// A price level containing all orders at a specific price point.
+///
+/// Hidden orders queue separately, behind every displayed order at the level.
 pub struct Level<O: OrderInterface> {
     price: O::N,
     orders: List<O>,
-    /// Total quantity across all orders (cached for performance).
+    hidden: List<O>,
+    /// Total displayed quantity across all orders (cached for performance).
     total_quantity: O::N,
+    /// Total quantity not displayed: hidden and all-or-none orders.
+    hidden_quantity: O::N,
+    /// Displayed slice of each iceberg order at this level.
+    icebergs: FxHashMap<O::I, O::N>,
 }
 
 impl<O: OrderInterface> Level<O> {
@@ -17,7 +26,10 @@ impl<O: OrderInterface> Level<O> {
         Self {
             price,
             orders: List::new(),
+            hidden: List::new(),
             total_quantity: O::N::default(),
+            hidden_quantity: O::N::default(),
+            icebergs: FxHashMap::default(),
         }
     }
 
@@ -31,24 +43,63 @@ impl<O: OrderInterface> Level<O> {
         self.total_quantity
     }
 
+    #[inline]
+    pub fn hidden_quantity(&self) -> O::N {
+        self.hidden_quantity
+    }
+
+    /// Returns the quantity displayed for `order`, which must rest at this level.
+    /// Hidden and all-or-none orders are not sliced, so they show their remaining.
+    #[inline(always)]
+    pub fn visible(&self, order: &O) -> O::N {
+        match order.display_quantity() {
+            Some(_) => self
+                .icebergs
+                .get(order.id())
+                .copied()
+                .unwrap_or_else(|| order.remaining()),
+            None => order.remaining(),
+        }
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
-        self.orders.len()
+        self.orders.len() + self.hidden.len()
     }
 
     #[inline(always)]
     pub fn is_empty(&self) -> bool {
-        self.orders.is_empty()
+        self.orders.is_empty() && self.hidden.is_empty()
+    }
+
+    /// Returns true if any displayed quantity rests at this level.
+    #[inline(always)]
+    pub fn is_displayed(&self) -> bool {
+        self.total_quantity > O::N::default()
     }
 
     /// Adds an order to this level (FIFO). Returns pointer to the inserted node.
     #[inline(always)]
     pub fn add_order(&mut self, order: O, pool: &mut Pool<O>) -> *mut Node<O> {
-        self.total_quantity += order.remaining();
+        match order.display_quantity() {
+            _ if order.hidden() => {
+                self.hidden_quantity += order.remaining();
+                return self.hidden.push_back(order, pool);
+            }
+            _ if order.all_or_none() => self.hidden_quantity += order.remaining(),
+            Some(display) => {
+                let slice = display.min(order.remaining());
+                self.total_quantity += slice;
+                self.icebergs.insert(order.id().clone(), slice);
+            }
+            None => self.total_quantity += order.remaining(),
+        }
         self.orders.push_back(order, pool)
     }
 
     /// Fills an order and returns true if fully filled.
+    /// An iceberg fill must not exceed its displayed slice; when the slice is used up the
+    /// next one is shown and the order moves to the back of the level.
     #[inline(always)]
     pub fn fill_order(
         &mut self,
@@ -58,31 +109,101 @@ impl<O: OrderInterface> Level<O> {
         pool: &mut Pool<O>,
     ) -> bool {
         order.fill(fill);
-        self.total_quantity -= fill;
+        if order.hidden() || order.all_or_none() {
+            self.hidden_quantity -= fill;
+        } else {
+            self.total_quantity -= fill;
+        }
         if order.remaining() == O::N::default() {
-            let _ = self.orders.remove_unchecked(node_ptr, pool);
+            if order.display_quantity().is_some() {
+                self.icebergs.remove(order.id());
+            }
+            let list = if order.hidden() {
+                &mut self.hidden
+            } else {
+                &mut self.orders
+            };
+            let _ = list.remove_unchecked(node_ptr, pool);
             return true;
         }
+        if let Some(display) = order.display_quantity()
+            && let Some(slice) = self.icebergs.get_mut(order.id())
+        {
+            *slice -= fill;
+            if *slice == O::N::default() {
+                *slice = display.min(order.remaining());
+                self.total_quantity += *slice;
+                self.orders.move_to_back(node_ptr);
+            }
+        }
         false
     }
 
+    /// Amends an order in place, keeping its queue position.
+    /// `remaining` must not exceed the order's current remaining quantity.
+    #[inline(always)]
+    pub fn amend_order(&mut self, order: &mut O, quantity: O::N, remaining: O::N) {
+        match order.display_quantity() {
+            _ if order.hidden() || order.all_or_none() => {
+                self.hidden_quantity -= order.remaining() - remaining
+            }
+            Some(_) => {
+                let slice = self.icebergs.get_mut(order.id()).unwrap();
+                let shown = (*slice).min(remaining);
+                self.total_quantity -= *slice - shown;
+                *slice = shown;
+            }
+            None => self.total_quantity -= order.remaining() - remaining,
+        }
+        order.amend(self.price, quantity);
+        let filled = quantity - remaining;
+        if filled > O::N::default() {
+            order.fill(filled);
+        }
+    }
+
+    /// Removes an order by its node pointer, which must be null or point into this level.
     #[inline(always)]
-    pub fn remove_order(&mut self, node_ptr: *mut Node<O>, pool: &mut Pool<O>) {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn remove_order(&mut self, node_ptr: *mut Node<O>, pool: &mut Pool<O>) -> Option<O> {
         if node_ptr.is_null() {
-            return;
+            return None;
         }
-        let order = self.orders.remove_unchecked(node_ptr, pool);
-        self.total_quantity -= order.remaining();
+        let list = if unsafe { (*node_ptr).data.hidden() } {
+            &mut self.hidden
+        } else {
+            &mut self.orders
+        };
+        let order = list.remove_unchecked(node_ptr, pool);
+        match order.display_quantity() {
+            _ if order.hidden() || order.all_or_none() => self.hidden_quantity -= order.remaining(),
+            Some(_) => self.total_quantity -= self.icebergs.remove(order.id()).unwrap(),
+            None => self.total_quantity -= order.remaining(),
+        }
+        Some(order)
+    }
+
+    /// Iterates orders in priority order: displayed orders FIFO, then hidden orders FIFO.
+    #[inline(always)]
+    pub fn iter(&self) -> Chain<Iter<'_, O>, Iter<'_, O>> {
+        self.orders.iter().chain(self.hidden.iter())
     }
 
+    /// Iterates displayed orders (including all-or-none) in FIFO order.
     #[inline(always)]
-    pub fn iter(&self) -> Iter<'_, O> {
+    pub fn iter_displayed(&self) -> Iter<'_, O> {
         self.orders.iter()
     }
 
+    /// Iterates hidden orders in FIFO order.
+    #[inline(always)]
+    pub fn iter_hidden(&self) -> Iter<'_, O> {
+        self.hidden.iter()
+    }
+
     #[inline(always)]
-    pub fn iter_mut(&mut self) -> IterMut<'_, O> {
-        self.orders.iter_mut()
+    pub fn iter_mut(&mut self) -> Chain<IterMut<'_, O>, IterMut<'_, O>> {
+        self.orders.iter_mut().chain(self.hidden.iter_mut())
     }
 }
 
@@ -143,6 +264,123 @@ mod tests {
         assert_eq!(level.len(), 1);
     }
 
+    #[test]
+    fn test_amend_order_keeps_position() {
+        let mut level = Level::<TestOrder>::new(100);
+        let mut pool = Pool::new();
+        let node_ptr = level.add_order(TestOrder::new("1", true, 100, 50), &mut pool);
+        level.add_order(TestOrder::new("2", true, 100, 30), &mut pool);
+        let order = unsafe { &mut (*node_ptr).data };
+        level.amend_order(order, 40, 20);
+        assert_eq!(level.total_quantity(), 50);
+        let ids: Vec<(&String, u64)> = level.iter().map(|o| (o.id(), o.remaining())).collect();
+        assert_eq!(
+            ids,
+            vec![(&String::from("1"), 20), (&String::from("2"), 30)]
+        );
+        assert_eq!(level.iter().next().unwrap().quantity(), 40);
+    }
+
+    #[test]
+    fn test_iceberg_display_and_refresh() {
+        let mut level = Level::<TestOrder>::new(100);
+        let mut pool = Pool::new();
+        let node_ptr = level.add_order(
+            TestOrder::new("1", true, 100, 250).with_display_quantity(100),
+            &mut pool,
+        );
+        level.add_order(TestOrder::new("2", true, 100, 30), &mut pool);
+        assert_eq!(level.total_quantity(), 130);
+
+        let order = unsafe { &mut (*node_ptr).data };
+        assert_eq!(level.visible(order), 100);
+        assert!(!level.fill_order(node_ptr, order, 60, &mut pool));
+        assert_eq!(level.total_quantity(), 70);
+        let ids: Vec<&String> = level.iter().map(|o| o.id()).collect();
+        assert_eq!(ids, vec!["1", "2"]);
+
+        // Slice used up: refresh from reserve and move to the back.
+        let order = unsafe { &mut (*node_ptr).data };
+        assert!(!level.fill_order(node_ptr, order, 40, &mut pool));
+        assert_eq!(level.visible(order), 100);
+        assert_eq!(level.total_quantity(), 130);
+        let ids: Vec<&String> = level.iter().map(|o| o.id()).collect();
+        assert_eq!(ids, vec!["2", "1"]);
+
+        // Reduce below the slice shrinks the display.
+        let order = unsafe { &mut (*node_ptr).data };
+        level.amend_order(order, 180, 30);
+        assert_eq!(level.total_quantity(), 60);
+
+        level.remove_order(node_ptr, &mut pool);
+        assert_eq!(level.total_quantity(), 30);
+        assert!(level.icebergs.is_empty());
+    }
+
+    #[test]
+    fn test_iceberg_full_fill() {
+        let mut level = Level::<TestOrder>::new(100);
+        let mut pool = Pool::new();
+        let node_ptr = level.add_order(
+            TestOrder::new("1", true, 100, 50).with_display_quantity(100),
+            &mut pool,
+        );
+        assert_eq!(level.total_quantity(), 50);
+        let order = unsafe { &mut (*node_ptr).data };
+        assert!(level.fill_order(node_ptr, order, 50, &mut pool));
+        assert!(level.is_empty());
+        assert!(level.icebergs.is_empty());
+    }
+
+    #[test]
+    fn test_all_or_none_not_displayed() {
+        let mut level = Level::<TestOrder>::new(100);
+        let mut pool = Pool::new();
+        let node_ptr = level.add_order(
+            TestOrder::new("1", true, 100, 500).with_all_or_none(true),
+            &mut pool,
+        );
+        level.add_order(TestOrder::new("2", true, 100, 30), &mut pool);
+        assert_eq!(level.total_quantity(), 30);
+        let order = unsafe { &mut (*node_ptr).data };
+        level.amend_order(order, 400, 400);
+        assert_eq!(level.total_quantity(), 30);
+        assert_eq!(level.hidden_quantity(), 400);
+        let order = unsafe { &mut (*node_ptr).data };
+        assert!(level.fill_order(node_ptr, order, 400, &mut pool));
+        assert_eq!(level.total_quantity(), 30);
+        assert_eq!(level.len(), 1);
+    }
+
+    #[test]
+    fn test_hidden_queues_behind_displayed() {
+        let mut level = Level::<TestOrder>::new(100);
+        let mut pool = Pool::new();
+        let node_ptr = level.add_order(
+            TestOrder::new("1", true, 100, 50).with_hidden(true),
+            &mut pool,
+        );
+        level.add_order(TestOrder::new("2", true, 100, 30), &mut pool);
+        level.add_order(
+            TestOrder::new("3", true, 100, 70).with_all_or_none(true),
+            &mut pool,
+        );
+        assert_eq!(level.total_quantity(), 30);
+        assert_eq!(level.hidden_quantity(), 120);
+        let ids: Vec<&String> = level.iter().map(|o| o.id()).collect();
+        assert_eq!(ids, vec!["2", "3", "1"]);
+
+        let order = unsafe { &mut (*node_ptr).data };
+        assert!(!level.fill_order(node_ptr, order, 20, &mut pool));
+        let order = unsafe { &mut (*node_ptr).data };
+        level.amend_order(order, 40, 10);
+        assert_eq!(level.hidden_quantity(), 80);
+        level.remove_order(node_ptr, &mut pool);
+        assert_eq!(level.hidden_quantity(), 70);
+        assert_eq!(level.total_quantity(), 30);
+        assert_eq!(level.len(), 2);
+    }
+
     #[test]
     fn test_iter() {
         let mut level = Level::<TestOrder>::new(100);
@@ -1,14 +1,29 @@
+mod alloc;
+mod auction;
+mod clock;
 mod eval;
+mod expiry;
 mod hash;
 mod level;
 mod list;
 mod ob;
 mod order;
+mod peg;
+mod phase;
+mod protect;
 mod side;
+mod stop;
 
+pub use alloc::{Allocation, LeadMakers};
+pub use clock::Clock;
 pub use eval::{Evaluator, Instruction, Msg, Op};
 pub use level::Level;
 pub use list::{List, Pool};
 pub use ob::*;
-pub use order::{OrderInterface, STP, TIF};
+pub use order::{
+    MinQty, Offset, OrderInterface, OrderType, Peg, PegRef, STP, StpKeys, StpScope, TIF, Trail,
+    TrailRef,
+};
+pub use phase::Phase;
+pub use protect::{Band, BandAction, BandRef, Collar, CollarAction, CollarRef};
 pub use side::Side;
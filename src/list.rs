@@ -49,6 +49,7 @@ impl<T> Pool<T> {
     }
 
     #[inline(always)]
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn dealloc(&mut self, ptr: *mut Node<T>) -> T {
         unsafe {
             let data = ptr::read(&(*ptr).data);
@@ -163,6 +164,30 @@ impl<T> List<T> {
         }
     }
 
+    /// Moves node at pointer to the back, keeping the node (and pointer) alive.
+    /// Caller must ensure pointer is valid and in this list.
+    #[inline(always)]
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn move_to_back(&mut self, node_ptr: *mut Node<T>) {
+        if node_ptr == self.tail {
+            return;
+        }
+        unsafe {
+            let prev = (*node_ptr).prev;
+            let next = (*node_ptr).next;
+            if prev.is_null() {
+                self.head = next;
+            } else {
+                (*prev).next = next;
+            }
+            (*next).prev = prev;
+            (*node_ptr).prev = self.tail;
+            (*node_ptr).next = ptr::null_mut();
+            (*self.tail).next = node_ptr;
+            self.tail = node_ptr;
+        }
+    }
+
     /// Removes node at pointer. Caller must ensure pointer is valid and in this list.
     #[inline(always)]
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
@@ -489,6 +514,33 @@ mod tests {
         assert!(list.is_empty());
     }
 
+    #[test]
+    fn test_move_to_back() {
+        let mut list = List::new();
+        let mut pool = Pool::new();
+        let node1 = list.push_back(1, &mut pool);
+        let node2 = list.push_back(2, &mut pool);
+        let node3 = list.push_back(3, &mut pool);
+
+        list.move_to_back(node1);
+        let vec: Vec<&i32> = list.iter().collect();
+        assert_eq!(vec, vec![&2, &3, &1]);
+
+        list.move_to_back(node3);
+        let vec: Vec<&i32> = list.iter().collect();
+        assert_eq!(vec, vec![&2, &1, &3]);
+
+        list.move_to_back(node3);
+        let vec: Vec<&i32> = list.iter().collect();
+        assert_eq!(vec, vec![&2, &1, &3]);
+        assert_eq!(list.len(), 3);
+
+        list.remove(node2, &mut pool);
+        list.remove(node3, &mut pool);
+        let vec: Vec<&i32> = list.iter().collect();
+        assert_eq!(vec, vec![&1]);
+    }
+
     #[test]
     fn test_pool_reuse() {
         let mut pool = Pool::new();
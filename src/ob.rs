@@ -1,12 +1,33 @@
-use crate::eval::Instruction;
-#[cfg(feature = "bench")]
-use crate::eval::Msg;
+use crate::eval::{Instruction, Msg, Op};
 use crate::{
+    alloc::{Allocation, LeadMakers},
+    auction::{self, Auction, Batch},
+    clock::Clock,
+    expiry::{DayOrders, Expiries},
     hash::FxHashMap,
     list::{Node, Pool},
     order::OrderInterface,
+    peg::Pegs,
+    phase::Phase,
+    protect::{Band, Collar},
     side::Side,
+    stop::Stops,
 };
+use std::collections::VecDeque;
+
+/// (Bid ID, Ask ID) of a two-sided quote.
+type QuoteIds<O> = (
+    Option<<O as OrderInterface>::I>,
+    Option<<O as OrderInterface>::I>,
+);
+
+/// Queue a held order waits in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Held {
+    Triggered,
+    Delayed,
+    Auction,
+}
 
 /// A complete orderbook with bid and ask sides.
 pub struct OrderBook<O: OrderInterface> {
@@ -14,6 +35,42 @@ pub struct OrderBook<O: OrderInterface> {
     pub(crate) asks: Side<O>,
     pub(crate) orders: FxHashMap<O::I, *mut Node<O>>,
     pub(crate) pool: Pool<O>,
+    /// Untriggered stop orders.
+    pub(crate) stops: Stops<O>,
+    /// Stops triggered by applied fills, waiting to be evaluated.
+    pub(crate) triggered: VecDeque<O>,
+    pub(crate) last_trade: Option<O::N>,
+    /// Resting pegged orders.
+    pub(crate) pegs: Pegs<O>,
+    /// Peg repricing ops, waiting to be evaluated.
+    pub(crate) repriced: VecDeque<Op<O>>,
+    /// GTD/GTT orders, stops and speed-bumped orders by expiry.
+    pub(crate) expiries: Expiries<O>,
+    /// DAY orders, resting or held, in arrival order.
+    pub(crate) day_orders: DayOrders<O>,
+    pub(crate) clock: Option<Box<dyn Clock>>,
+    /// Minimum price increment, used to slide post-only orders.
+    pub(crate) tick_size: Option<O::N>,
+    /// How fills are shared among makers at a level.
+    pub(crate) allocation: Allocation,
+    pub(crate) lead_makers: Option<LeadMakers<O>>,
+    /// Limit on how far an incoming order may sweep.
+    pub(crate) collar: Option<Collar<O::N>>,
+    /// Price band; a breach may halt the book.
+    pub(crate) band: Option<Band<O::N>>,
+    pub(crate) phase: Phase,
+    /// Market orders held for the uncross in a call phase.
+    pub(crate) auction: Auction<O>,
+    /// Frequent batch auction schedule, if enabled.
+    pub(crate) batch: Option<Batch>,
+    /// Clock ticks liquidity-taking orders wait before matching.
+    pub(crate) speed_bump: Option<u64>,
+    /// Orders held by the speed bump: (Release Time, Order), in arrival order.
+    pub(crate) delayed: VecDeque<(u64, O)>,
+    /// Queue of each triggered stop, speed-bumped order and held market order.
+    pub(crate) held: FxHashMap<O::I, Held>,
+    /// (Bid ID, Ask ID) of each owner's last quote; either may since have left the book.
+    pub(crate) quotes: FxHashMap<O::O, QuoteIds<O>>,
 }
 
 impl<O: OrderInterface> Default for OrderBook<O> {
@@ -23,6 +80,26 @@ impl<O: OrderInterface> Default for OrderBook<O> {
             asks: Side::new(false),
             orders: FxHashMap::default(),
             pool: Pool::new(),
+            stops: Stops::default(),
+            triggered: VecDeque::new(),
+            last_trade: None,
+            pegs: Pegs::default(),
+            repriced: VecDeque::new(),
+            expiries: Expiries::default(),
+            day_orders: DayOrders::default(),
+            clock: None,
+            tick_size: None,
+            allocation: Allocation::Fifo,
+            lead_makers: None,
+            collar: None,
+            band: None,
+            phase: Phase::Continuous,
+            auction: Auction::default(),
+            batch: None,
+            speed_bump: None,
+            delayed: VecDeque::new(),
+            held: FxHashMap::default(),
+            quotes: FxHashMap::default(),
         }
     }
 }
@@ -37,6 +114,17 @@ pub enum Output<O: OrderInterface> {
     Partial(O::I),
     // Filled
     Filled(O::I),
+    // Replaced, with resting quantity
+    Replaced(O::I, O::N),
+    // Reduced in place, with resting quantity
+    Reduced(O::I, O::N),
+    // Held as an untriggered stop, a market order for the call auction, or by the speed
+    // bump
+    Held(O::I),
+    // Phase changed
+    Phase(Phase),
+    // Owner's quotes recorded
+    Quoted(O::O),
     // No operation
     NoOp(O::I),
 }
@@ -46,6 +134,82 @@ pub enum Output<O: OrderInterface> {
 // ─────────────────────────────────────────────────────────────────────────────
 
 impl<O: OrderInterface> OrderBook<O> {
+    /// Creates an empty book reading time from `clock`.
+    #[inline]
+    pub fn with_clock(clock: impl Clock + 'static) -> Self {
+        Self {
+            clock: Some(Box::new(clock)),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the minimum price increment.
+    #[inline]
+    pub fn with_tick_size(mut self, tick_size: O::N) -> Self {
+        self.tick_size = Some(tick_size);
+        self
+    }
+
+    /// Sets how fills are shared among makers at a level. Default is FIFO.
+    #[inline]
+    pub fn with_allocation(mut self, allocation: Allocation) -> Self {
+        self.allocation = allocation;
+        self
+    }
+
+    /// Sets the lead market makers and their entitlement.
+    ///
+    /// Panics if the share's denominator is zero or the share is above one.
+    #[inline]
+    pub fn with_lead_makers(mut self, lead_makers: LeadMakers<O>) -> Self {
+        let (num, den) = lead_makers.share;
+        assert!(
+            den > O::N::default() && num <= den,
+            "lead maker share must be a fraction of at most one"
+        );
+        self.lead_makers = Some(lead_makers);
+        self
+    }
+
+    /// Sets the price collar for incoming orders.
+    #[inline]
+    pub fn with_collar(mut self, collar: Collar<O::N>) -> Self {
+        self.collar = Some(collar);
+        self
+    }
+
+    /// Sets the price band (circuit breaker).
+    #[inline]
+    pub fn with_band(mut self, band: Band<O::N>) -> Self {
+        self.band = Some(band);
+        self
+    }
+
+    /// Sets a speed bump: new orders that would take liquidity on arrival are held for
+    /// `delay` clock ticks, then released by `pop_pending` in arrival order and matched
+    /// against the book as it is then. Cancels, passive orders, post-only and pegged
+    /// orders and stops go through at once; replaces that would take liquidity are
+    /// refused with `Msg::SpeedBump`. Needs a clock.
+    #[inline]
+    pub fn with_speed_bump(mut self, delay: u64) -> Self {
+        self.speed_bump = Some(delay);
+        self
+    }
+
+    /// Enables frequent batch auctions: in the continuous phase orders rest without
+    /// matching, as in a call phase, and the book is uncrossed at one price at the end of
+    /// every `interval` clock ticks. Batches end at multiples of `interval` on the book
+    /// clock; `pop_pending` yields the `Op::Uncross` once one has ended. Needs a clock.
+    ///
+    /// Panics if `interval` is zero.
+    #[inline]
+    pub fn with_batch_auction(mut self, interval: u64) -> Self {
+        assert!(interval > 0, "batch auction interval must be non-zero");
+        let current = self.now().map_or(0, |now| now / interval);
+        self.batch = Some(Batch { interval, current });
+        self
+    }
+
     // ─────────────────────────────────────────────────────────────────────────
     // Getters
     // ─────────────────────────────────────────────────────────────────────────
@@ -62,25 +226,27 @@ impl<O: OrderInterface> OrderBook<O> {
         self.asks.iter()
     }
 
-    /// Returns the best (highest) bid as (price, total_quantity), if any.
+    /// Returns the best (highest) displayed bid as (price, total_quantity), if any.
     #[inline]
     pub fn best_bid(&self) -> Option<(O::N, O::N)> {
         self.bids.best()
     }
 
-    /// Returns the best (lowest) ask as (price, total_quantity), if any.
+    /// Returns the best (lowest) displayed ask as (price, total_quantity), if any.
     #[inline]
     pub fn best_ask(&self) -> Option<(O::N, O::N)> {
         self.asks.best()
     }
 
-    /// Returns the top `n` bid levels as (price, total_quantity), highest price first.
+    /// Returns the top `n` displayed bid levels as (price, total_quantity), highest price
+    /// first.
     #[inline]
     pub fn top_bids(&self, n: usize) -> Vec<(O::N, O::N)> {
         self.bids.top(n)
     }
 
-    /// Returns the top `n` ask levels as (price, total_quantity), lowest price first.
+    /// Returns the top `n` displayed ask levels as (price, total_quantity), lowest price
+    /// first.
     #[inline]
     pub fn top_asks(&self, n: usize) -> Vec<(O::N, O::N)> {
         self.asks.top(n)
@@ -118,10 +284,309 @@ impl<O: OrderInterface> OrderBook<O> {
             .map(|&ptr| unsafe { &(*ptr).data })
     }
 
+    /// Returns the price of the last trade, if any.
+    #[inline]
+    pub fn last_trade_price(&self) -> Option<O::N> {
+        self.last_trade
+    }
+
+    /// Returns the number of untriggered stop orders.
+    #[inline]
+    pub fn stop_count(&self) -> usize {
+        self.stops.len()
+    }
+
+    /// Returns a reference to the untriggered stop order with the given ID, if it exists.
+    #[inline]
+    pub fn stop(&self, order_id: &O::I) -> Option<&O> {
+        self.stops.get(order_id)
+    }
+
+    /// Returns the current trigger price of an untriggered stop, if it exists.
+    /// For trailing stops this follows the market; it is `None` until a reference exists.
+    #[inline]
+    pub fn stop_trigger(&self, order_id: &O::I) -> Option<O::N> {
+        self.stops.trigger_price(order_id)
+    }
+
+    /// Returns the number of resting pegged orders.
+    #[inline]
+    pub fn peg_count(&self) -> usize {
+        self.pegs.len()
+    }
+
+    /// Returns the current time from the book's clock, if one is set.
+    #[inline]
+    pub fn now(&self) -> Option<u64> {
+        self.clock.as_ref().map(|clock| clock.now())
+    }
+
+    /// Returns the minimum price increment, if set.
+    #[inline]
+    pub fn tick_size(&self) -> Option<O::N> {
+        self.tick_size
+    }
+
+    /// Returns how fills are shared among makers at a level.
+    #[inline]
+    pub fn allocation(&self) -> Allocation {
+        self.allocation
+    }
+
+    /// Returns the lead market makers, if set.
+    #[inline]
+    pub fn lead_makers(&self) -> Option<&LeadMakers<O>> {
+        self.lead_makers.as_ref()
+    }
+
+    /// Returns the price collar, if set.
+    #[inline]
+    pub fn collar(&self) -> Option<Collar<O::N>> {
+        self.collar
+    }
+
+    /// Returns the price band, if set.
+    #[inline]
+    pub fn band(&self) -> Option<Band<O::N>> {
+        self.band
+    }
+
+    /// Returns `owner`'s resting (bid, ask) quotes.
+    #[inline]
+    pub fn quotes(&self, owner: &O::O) -> (Option<&O>, Option<&O>) {
+        match self.quotes.get(owner) {
+            Some((bid, ask)) => (
+                bid.as_ref().and_then(|id| self.order(id)),
+                ask.as_ref().and_then(|id| self.order(id)),
+            ),
+            None => (None, None),
+        }
+    }
+
+    /// Returns the speed bump delay in clock ticks, if set.
+    #[inline]
+    pub fn speed_bump(&self) -> Option<u64> {
+        self.speed_bump
+    }
+
+    /// Returns the number of orders held by the speed bump.
+    #[inline]
+    pub fn delayed_count(&self) -> usize {
+        self.delayed.len()
+    }
+
+    /// Returns the frequent batch auction interval, if enabled.
+    #[inline]
+    pub fn batch_interval(&self) -> Option<u64> {
+        self.batch.as_ref().map(|batch| batch.interval)
+    }
+
+    /// Returns the trading phase.
+    #[inline]
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Returns the number of market orders held for the call auction.
+    #[inline]
+    pub fn auction_count(&self) -> usize {
+        self.auction.len()
+    }
+
+    /// Returns a market order held for the call auction.
+    #[inline]
+    pub fn auction_order(&self, order_id: &O::I) -> Option<&O> {
+        self.auction.get(order_id)
+    }
+
+    /// Returns the indicative uncross (price, volume) in a call phase or batch, or
+    /// `None` if nothing would trade.
+    #[inline]
+    pub fn indicative(&self) -> Option<(O::N, O::N)> {
+        if !self.collects(self.phase) {
+            return None;
+        }
+        auction::equilibrium(&self.bids, &self.asks, &self.auction, self.last_trade)
+    }
+
+    /// Returns the number of DAY orders, resting or held, that end with the session.
+    #[inline]
+    pub fn day_count(&self) -> usize {
+        self.day_orders.len()
+    }
+
+    /// Returns the number of GTD/GTT orders, stops and speed-bumped orders waiting to
+    /// expire.
+    #[inline]
+    pub fn expiry_count(&self) -> usize {
+        self.expiries.len()
+    }
+
+    /// Returns a delete for every GTD/GTT order, stop or speed-bumped order expiring at or
+    /// before `now`, earliest expiry first, then FIFO. Apply them to remove the orders.
+    /// Only touches expired entries; pass `self.now()` to expire against the book clock.
+    #[inline]
+    pub fn expire_until(&self, now: u64) -> Vec<Instruction<O>> {
+        self.expiries
+            .due(now)
+            .map(|id| Instruction::Delete(id.clone(), Msg::Expired))
+            .collect()
+    }
+
+    /// Returns a delete for every DAY order, leaving other orders resting. Apply them at
+    /// the session boundary. Covers resting orders, stops (triggered or not), market
+    /// orders held for the call auction and orders held by the speed bump, in arrival
+    /// order; a stop or held order that comes to rest counts as arriving then.
+    pub fn end_session(&self) -> Vec<Instruction<O>> {
+        self.day_orders
+            .iter()
+            .map(|id| Instruction::Delete(id.clone(), Msg::SessionEnd))
+            .collect()
+    }
+
+    /// Returns the instructions that uncross the book in a call phase at its equilibrium
+    /// price (see `indicative`), using the last trade as the reference price: a fill for
+    /// every matched order at that price, then a delete for every held market order left
+    /// unfilled. Empty unless orders are being collected (a call phase or batch).
+    /// All-or-none orders sit out and resting minimum quantities are not applied.
+    ///
+    /// Leaving a call phase with `Op::Phase` uncrosses the book, as does `Op::Uncross`,
+    /// so this is only needed outside the evaluator.
+    pub fn uncross(&self) -> Vec<Instruction<O>> {
+        if !self.collects(self.phase) {
+            return Vec::new();
+        }
+        auction::uncross(&self.bids, &self.asks, &self.auction, self.last_trade)
+    }
+
+    /// Pops the next op generated by the book itself, such as a triggered stop.
+    /// Evaluate and apply each one until this returns `None` to run cascades in order:
+    /// stops triggered by the same trade are released lowest stop price first for buys
+    /// (highest for sells), then FIFO; stops triggered later queue behind them.
+    ///
+    /// Once no stops are waiting, orders held by the speed bump are released as
+    /// `Op::Trigger` once their release time is reached on the book clock, in arrival
+    /// order.
+    ///
+    /// Then a frequent batch auction whose batch has ended on the
+    /// book clock yields an `Op::Uncross`. Call this between ops to clear batches on time.
+    ///
+    /// Then pegged orders are repriced if the best unpegged bid or
+    /// ask moved: one `Op::Replace` per peg whose price changed, in arrival order. A peg
+    /// whose reference is gone stays at its last price.
+    ///
+    /// While halted or closed, triggered stops, released orders and peg reprices are kept
+    /// back and come out once the book resumes.
+    #[inline]
+    pub fn pop_pending(&mut self) -> Option<Op<O>> {
+        let held = matches!(self.phase, Phase::Halted | Phase::Closed);
+        if !held && let Some(order) = self.triggered.pop_front() {
+            // Re-indexed if it comes to rest on the book.
+            self.held.remove(order.id());
+            self.day_orders.remove(order.id());
+            return Some(Op::Trigger(order));
+        }
+        let now = self.now();
+        if !held
+            && let Some(now) = now
+            && self.delayed.front().is_some_and(|&(at, _)| at <= now)
+            && let Some((_, order)) = self.delayed.pop_front()
+        {
+            // Re-indexed if it comes to rest on the book.
+            if let Some(at) = Expiries::expiry(&order) {
+                self.expiries.remove(at, order.id());
+            }
+            self.held.remove(order.id());
+            self.day_orders.remove(order.id());
+            return Some(Op::Trigger(order));
+        }
+        if let Some(now) = now
+            && let Some(batch) = &mut self.batch
+            && batch.roll(now)
+        {
+            return Some(Op::Uncross);
+        }
+        if held {
+            return None;
+        }
+        if self.repriced.is_empty()
+            && let Some((best_bid, best_ask)) = self.pegs.take_dirty()
+        {
+            self.reprice_pegs(best_bid, best_ask);
+        }
+        self.repriced.pop_front()
+    }
+
     // ─────────────────────────────────────────────────────────────────────────
     // Internal helpers
     // ─────────────────────────────────────────────────────────────────────────
 
+    /// Returns true if `order_id` is resting, held as a stop (triggered or not), held for
+    /// the call auction, or held by the speed bump.
+    #[inline(always)]
+    pub(crate) fn contains(&self, order_id: &O::I) -> bool {
+        self.orders.contains_key(order_id)
+            || self.stops.contains(order_id)
+            || self.held.contains_key(order_id)
+    }
+
+    /// Returns true if orders rest without matching in `phase`: a call phase, or the
+    /// continuous phase with frequent batch auctions.
+    #[inline(always)]
+    pub(crate) fn collects(&self, phase: Phase) -> bool {
+        phase.is_call() || (phase == Phase::Continuous && self.batch.is_some())
+    }
+
+    /// Returns the price one tick behind `opposite` for a post-only order on the given
+    /// side, or `None` without a tick size or below the lowest price.
+    #[inline]
+    pub(crate) fn slide(&self, is_buy: bool, opposite: O::N) -> Option<O::N> {
+        let tick = self.tick_size?;
+        if !is_buy {
+            Some(opposite + tick)
+        } else if opposite > tick {
+            Some(opposite - tick)
+        } else {
+            None
+        }
+    }
+
+    /// Marks stops triggered from `start` onward as held and drops them from the expiry
+    /// index; they are re-indexed if they come to rest on the book.
+    #[inline]
+    fn index_triggered(
+        expiries: &mut Expiries<O>,
+        held: &mut FxHashMap<O::I, Held>,
+        triggered: &VecDeque<O>,
+        start: usize,
+    ) {
+        for order in triggered.range(start..) {
+            held.insert(order.id().clone(), Held::Triggered);
+            if !expiries.is_empty()
+                && let Some(at) = Expiries::expiry(order)
+            {
+                expiries.remove(at, order.id());
+            }
+        }
+    }
+
+    /// Queues a replace for every peg whose price no longer matches the references.
+    fn reprice_pegs(&mut self, best_bid: Option<O::N>, best_ask: Option<O::N>) {
+        for id in self.pegs.iter() {
+            let order = unsafe { &(*self.orders[id]).data };
+            let peg = order.peg().unwrap();
+            if let Some(price) = peg.price(order.is_buy(), best_bid, best_ask)
+                && price != order.price()
+            {
+                self.repriced.push_back(Op::Replace {
+                    id: id.clone(),
+                    new_price: price,
+                    new_quantity: order.quantity(),
+                });
+            }
+        }
+    }
+
     /// Applies a single insert instruction. Only available with the `bench` feature.
     #[cfg(feature = "bench")]
     #[inline(always)]
@@ -139,23 +604,80 @@ impl<O: OrderInterface> OrderBook<O> {
     /// Applies a single instruction to the orderbook, mutating state.
     #[inline]
     pub fn apply(&mut self, instruction: Instruction<O>) -> Output<O> {
+        let output = self.apply_instruction(instruction);
+        if self.stops.has_trailing() {
+            let best_bid = self.bids.best().map(|(price, _)| price);
+            let best_ask = self.asks.best().map(|(price, _)| price);
+            let start = self.triggered.len();
+            self.stops
+                .update_trailing(best_bid, best_ask, self.last_trade, &mut self.triggered);
+            Self::index_triggered(&mut self.expiries, &mut self.held, &self.triggered, start);
+        }
+        if !self.pegs.is_empty() {
+            self.pegs
+                .update(self.bids.best_unpegged(), self.asks.best_unpegged());
+        }
+        output
+    }
+
+    #[inline(always)]
+    fn apply_instruction(&mut self, instruction: Instruction<O>) -> Output<O> {
         let Self {
             bids,
             asks,
             orders,
             pool,
+            stops,
+            triggered,
+            last_trade,
+            pegs,
+            expiries,
+            day_orders,
+            held,
+            ..
         } = self;
         match instruction {
-            Instruction::Fill(order_id, _, _price, quantity, is_taker) => {
+            Instruction::Fill(order_id, _, price, quantity, is_taker) => {
                 if is_taker {
                     return Output::Filled(order_id);
                 }
-                let &node_ptr = orders.get(&order_id).unwrap();
-                let is_buy = unsafe { (*node_ptr).data.is_buy() };
+                *last_trade = Some(price);
+                if !stops.is_empty() {
+                    let start = triggered.len();
+                    stops.trigger(price, triggered);
+                    Self::index_triggered(expiries, held, triggered, start);
+                }
+                let Some(&node_ptr) = orders.get(&order_id) else {
+                    // A market order held for the call auction.
+                    if self.auction.fill(&order_id, quantity) {
+                        held.remove(&order_id);
+                        day_orders.remove(&order_id);
+                        return Output::Filled(order_id);
+                    }
+                    return Output::Partial(order_id);
+                };
+                let (is_buy, pegged, expiry, day) = unsafe {
+                    let order = &(*node_ptr).data;
+                    (
+                        order.is_buy(),
+                        order.peg().is_some(),
+                        Expiries::expiry(order),
+                        DayOrders::is_day(order),
+                    )
+                };
                 let side = if is_buy { bids } else { asks };
                 let removed = side.fill_order(node_ptr, quantity, pool);
                 if removed {
                     orders.remove(&order_id);
+                    if pegged {
+                        pegs.remove(&order_id);
+                    }
+                    if let Some(at) = expiry {
+                        expiries.remove(at, &order_id);
+                    }
+                    if day {
+                        day_orders.remove(&order_id);
+                    }
                     Output::Filled(order_id)
                 } else {
                     Output::Partial(order_id)
@@ -169,6 +691,15 @@ impl<O: OrderInterface> OrderBook<O> {
                     }
                     let id = order.id().clone();
                     let is_buy = order.is_buy();
+                    if order.peg().is_some() {
+                        pegs.insert(id.clone());
+                    }
+                    if let Some(at) = Expiries::expiry(&order) {
+                        expiries.insert(at, id.clone());
+                    }
+                    if DayOrders::is_day(&order) {
+                        day_orders.insert(id.clone());
+                    }
                     let side = if is_buy { bids } else { asks };
                     let node_ptr = side.insert_order(order, pool);
                     orders.insert(id.clone(), node_ptr);
@@ -179,14 +710,135 @@ impl<O: OrderInterface> OrderBook<O> {
                 }
             }
             Instruction::Delete(order_id, _msg) => {
+                if !day_orders.is_empty() {
+                    day_orders.remove(&order_id);
+                }
                 if let Some(&node_ptr) = orders.get(&order_id) {
                     let is_buy = unsafe { (*node_ptr).data.is_buy() };
                     let side = if is_buy { bids } else { asks };
-                    side.remove_order(node_ptr, pool);
+                    let order = side.remove_order(node_ptr, pool).unwrap();
                     orders.remove(&order_id);
+                    if order.peg().is_some() {
+                        pegs.remove(&order_id);
+                    }
+                    if let Some(at) = Expiries::expiry(&order) {
+                        expiries.remove(at, &order_id);
+                    }
+                } else if let Some(order) = stops.remove(&order_id) {
+                    if let Some(at) = Expiries::expiry(&order) {
+                        expiries.remove(at, &order_id);
+                    }
+                } else {
+                    match held.remove(&order_id) {
+                        Some(Held::Triggered) => {
+                            // Triggered stops are off the expiry index until they rest.
+                            let pos = triggered.iter().position(|order| *order.id() == order_id);
+                            triggered.remove(pos.unwrap());
+                        }
+                        Some(Held::Delayed) => {
+                            let pos = self
+                                .delayed
+                                .iter()
+                                .position(|(_, order)| *order.id() == order_id);
+                            let (_, order) = self.delayed.remove(pos.unwrap()).unwrap();
+                            if let Some(at) = Expiries::expiry(&order) {
+                                expiries.remove(at, &order_id);
+                            }
+                        }
+                        Some(Held::Auction) => {
+                            self.auction.remove(&order_id);
+                        }
+                        None => {}
+                    }
                 }
                 Output::Deleted(order_id)
             }
+            Instruction::Replace(order_id, price, quantity, remaining) => {
+                let &node_ptr = orders.get(&order_id).unwrap();
+                let (is_buy, old_price, old_remaining) = unsafe {
+                    let order = &(*node_ptr).data;
+                    (order.is_buy(), order.price(), order.remaining())
+                };
+                let side = if is_buy { bids } else { asks };
+                if price == old_price && remaining <= old_remaining {
+                    side.amend_order(node_ptr, quantity, remaining);
+                    return Output::Replaced(order_id, remaining);
+                }
+                let mut order = side.remove_order(node_ptr, pool).unwrap();
+                orders.remove(&order_id);
+                if remaining == O::N::default() {
+                    if order.peg().is_some() {
+                        pegs.remove(&order_id);
+                    }
+                    if let Some(at) = Expiries::expiry(&order) {
+                        expiries.remove(at, &order_id);
+                    }
+                    if DayOrders::is_day(&order) {
+                        day_orders.remove(&order_id);
+                    }
+                    return Output::Filled(order_id);
+                }
+                order.amend(price, quantity);
+                let filled = quantity - remaining;
+                if filled > O::N::default() {
+                    order.fill(filled);
+                }
+                let node_ptr = side.insert_order(order, pool);
+                orders.insert(order_id.clone(), node_ptr);
+                Output::Replaced(order_id, remaining)
+            }
+            Instruction::Reduce(order_id, quantity) => {
+                let &node_ptr = orders.get(&order_id).unwrap();
+                let is_buy = unsafe { (*node_ptr).data.is_buy() };
+                let side = if is_buy { bids } else { asks };
+                side.reduce_order(node_ptr, quantity);
+                let remaining = unsafe { (*node_ptr).data.remaining() };
+                Output::Reduced(order_id, remaining)
+            }
+            Instruction::Hold(order) => {
+                let id = order.id().clone();
+                if let Some(at) = Expiries::expiry(&order) {
+                    expiries.insert(at, id.clone());
+                }
+                if DayOrders::is_day(&order) {
+                    day_orders.insert(id.clone());
+                }
+                stops.insert(order);
+                Output::Held(id)
+            }
+            Instruction::Delay(order, release) => {
+                let id = order.id().clone();
+                if let Some(at) = Expiries::expiry(&order) {
+                    expiries.insert(at, id.clone());
+                }
+                if DayOrders::is_day(&order) {
+                    day_orders.insert(id.clone());
+                }
+                held.insert(id.clone(), Held::Delayed);
+                self.delayed.push_back((release, order));
+                Output::Held(id)
+            }
+            Instruction::Quote(owner, bid, ask) => {
+                if bid.is_none() && ask.is_none() {
+                    self.quotes.remove(&owner);
+                } else {
+                    self.quotes.insert(owner.clone(), (bid, ask));
+                }
+                Output::Quoted(owner)
+            }
+            Instruction::Auction(order) => {
+                let id = order.id().clone();
+                if DayOrders::is_day(&order) {
+                    day_orders.insert(id.clone());
+                }
+                held.insert(id.clone(), Held::Auction);
+                self.auction.insert(order);
+                Output::Held(id)
+            }
+            Instruction::Phase(phase) => {
+                self.phase = phase;
+                Output::Phase(phase)
+            }
             Instruction::NoOp(order_id, _) => Output::NoOp(order_id),
         }
     }
@@ -196,19 +848,43 @@ impl<O: OrderInterface> OrderBook<O> {
 mod tests {
     use super::*;
     use crate::eval::{Evaluator, Instruction, Msg, Op};
-    use crate::order::{STP, TIF, TestOrder};
+    use crate::order::{
+        Offset, OrderType, Peg, PegRef, STP, StpScope, TIF, TestOrder, Trail, TrailRef,
+    };
+    use crate::protect::{BandAction, BandRef, CollarAction, CollarRef};
 
-    fn setup_order(ob: &mut OrderBook<TestOrder>, id: &str, is_buy: bool, price: u64, qty: u64) {
-        let order = TestOrder::new(id, is_buy, price, qty);
+    fn setup(ob: &mut OrderBook<TestOrder>, order: TestOrder) {
         let OrderBook {
             bids,
             asks,
             orders,
             pool,
+            ..
         } = ob;
-        let side = if is_buy { bids } else { asks };
+        let id = order.id().clone();
+        let side = if order.is_buy() { bids } else { asks };
         let node_ptr = side.insert_order(order, pool);
-        orders.insert(String::from(id), node_ptr);
+        orders.insert(id, node_ptr);
+    }
+
+    /// Evaluates and applies `op`, then any book-generated ops, returning every instruction.
+    fn drive(ob: &mut OrderBook<TestOrder>, op: Op<TestOrder>) -> Vec<Instruction<TestOrder>> {
+        let mut eval = Evaluator::default();
+        let mut all = Vec::new();
+        let mut next = Some(op);
+        while let Some(op) = next {
+            eval.reset();
+            for instr in eval.eval(ob, op) {
+                all.push(instr.clone());
+                ob.apply(instr);
+            }
+            next = ob.pop_pending();
+        }
+        all
+    }
+
+    fn setup_order(ob: &mut OrderBook<TestOrder>, id: &str, is_buy: bool, price: u64, qty: u64) {
+        setup(ob, TestOrder::new(id, is_buy, price, qty));
     }
 
     fn setup_order_with_owner(
@@ -219,16 +895,7 @@ mod tests {
         qty: u64,
         owner: &str,
     ) {
-        let order = TestOrder::new(id, is_buy, price, qty).with_owner(owner);
-        let OrderBook {
-            bids,
-            asks,
-            orders,
-            pool,
-        } = ob;
-        let side = if is_buy { bids } else { asks };
-        let node_ptr = side.insert_order(order, pool);
-        orders.insert(String::from(id), node_ptr);
+        setup(ob, TestOrder::new(id, is_buy, price, qty).with_owner(owner));
     }
 
     // ─────────────────────────────────────────────────────────────────────────
@@ -633,7 +1300,7 @@ mod tests {
         let mut ob = OrderBook::<TestOrder>::default();
         setup_order(&mut ob, "1", false, 1000, 100);
         let t1 = TestOrder::new("t1", true, 1000, 100);
-        for instr in vec![
+        for instr in [
             Instruction::Fill(String::from("1"), String::from("1"), 1000, 30, false),
             Instruction::Insert(t1, 70),
         ] {
@@ -644,7 +1311,7 @@ mod tests {
         // Complete fill sell
         let mut ob = OrderBook::<TestOrder>::default();
         setup_order(&mut ob, "1", false, 1000, 100);
-        for instr in vec![
+        for instr in [
             Instruction::Fill(String::from("t1"), String::from("t1"), 1000, 100, true),
             Instruction::Fill(String::from("1"), String::from("1"), 1000, 100, false),
         ] {
@@ -656,7 +1323,7 @@ mod tests {
         let mut ob = OrderBook::<TestOrder>::default();
         setup_order(&mut ob, "1", true, 1000, 100);
         let t1 = TestOrder::new("t1", false, 1000, 100);
-        for instr in vec![
+        for instr in [
             Instruction::Fill(String::from("1"), String::from("1"), 1000, 30, false),
             Instruction::Insert(t1, 70),
         ] {
@@ -667,7 +1334,7 @@ mod tests {
         // Complete fill buy
         let mut ob = OrderBook::<TestOrder>::default();
         setup_order(&mut ob, "1", true, 1000, 100);
-        for instr in vec![
+        for instr in [
             Instruction::Fill(String::from("t1"), String::from("t1"), 1000, 100, true),
             Instruction::Fill(String::from("1"), String::from("1"), 1000, 100, false),
         ] {
@@ -818,6 +1485,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_post_only_slide() {
+        let mut ob = OrderBook::<TestOrder>::default().with_tick_size(5);
+        setup_order(&mut ob, "s1", false, 1000, 100);
+        setup_order(&mut ob, "b0", true, 990, 100);
+        let order = TestOrder::new("b1", true, 1010, 100).with_post_only_slide();
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i,
+            vec![Instruction::Insert(
+                TestOrder::new("b1", true, 995, 100).with_post_only_slide(),
+                100
+            )]
+        );
+        assert_eq!(ob.best_bid(), Some((995, 100)));
+
+        let order = TestOrder::new("s2", false, 900, 50).with_post_only_slide();
+        drive(&mut ob, Op::Insert(order));
+        assert_eq!(ob.order(&String::from("s2")).unwrap().price(), 1000);
+        assert_eq!(ob.best_ask(), Some((1000, 150)));
+    }
+
+    #[test]
+    fn test_post_only_slide_needs_tick_size() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 100);
+        let order = TestOrder::new("b1", true, 1000, 100).with_post_only_slide();
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b1"), Msg::PostOnlyFilled)]
+        );
+    }
+
     #[test]
     fn test_post_only_accept_if_maker() {
         let mut ob = OrderBook::<TestOrder>::default();
@@ -829,136 +1530,2656 @@ mod tests {
     }
 
     // ─────────────────────────────────────────────────────────────────────────
-    // STP (self-trade prevention) tests
+    // Market order tests
     // ─────────────────────────────────────────────────────────────────────────
 
     #[test]
-    fn test_stp_cancel_taker() {
+    fn test_market_sweeps_without_limit() {
         let mut ob = OrderBook::<TestOrder>::default();
-        setup_order_with_owner(&mut ob, "s1", false, 1000, 100, "alice");
-        let order = TestOrder::new("b1", true, 1000, 100)
-            .with_owner("alice")
-            .with_stp(STP::CancelTaker);
+        setup_order(&mut ob, "s1", false, 1000, 50);
+        setup_order(&mut ob, "s2", false, 2000, 50);
+        // Price is ignored for market orders.
+        let order = TestOrder::new("b1", true, 0, 100).with_order_type(OrderType::Market);
         let mut eval = Evaluator::default();
         let i: Vec<_> = eval.eval(&ob, Op::Insert(order)).collect();
         assert_eq!(
             i,
-            vec![Instruction::NoOp(String::from("b1"), Msg::StpCancelTaker)]
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1500, 100, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 50, false),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 2000, 50, false),
+            ]
         );
     }
 
     #[test]
-    fn test_stp_cancel_taker_no_self_trade() {
+    fn test_market_leftover_cancelled() {
         let mut ob = OrderBook::<TestOrder>::default();
-        setup_order_with_owner(&mut ob, "s1", false, 1000, 100, "alice");
-        let order = TestOrder::new("b1", true, 1000, 100)
-            .with_owner("bob")
-            .with_stp(STP::CancelTaker);
+        setup_order(&mut ob, "b1", true, 1000, 50);
+        let order = TestOrder::new("s1", false, 0, 100).with_order_type(OrderType::Market);
         let mut eval = Evaluator::default();
-        let i: Vec<_> = eval.eval(&ob, Op::Insert(order.clone())).collect();
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(order)).collect();
         assert_eq!(
             i,
             vec![
-                Instruction::Fill(String::from("b1"), String::from("bob"), 1000, 100, true),
-                Instruction::Fill(String::from("s1"), String::from("alice"), 1000, 100, false),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 50, true),
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 50, false),
+                Instruction::Delete(String::from("s1"), Msg::MarketLeftover),
             ]
         );
+        for instr in i {
+            ob.apply(instr);
+        }
+        assert!(ob.is_empty());
     }
 
     #[test]
-    fn test_stp_cancel_maker() {
+    fn test_market_no_liquidity() {
+        let ob = OrderBook::<TestOrder>::default();
+        let mut eval = Evaluator::default();
+        for order_type in [OrderType::Market, OrderType::MarketToLimit] {
+            let order = TestOrder::new("b1", true, 0, 100).with_order_type(order_type);
+            let i: Vec<_> = eval.eval(&ob, Op::Insert(order)).collect();
+            assert_eq!(
+                i,
+                vec![Instruction::NoOp(String::from("b1"), Msg::MarketNoFill)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_market_fok_and_ioc() {
         let mut ob = OrderBook::<TestOrder>::default();
-        setup_order_with_owner(&mut ob, "s1", false, 1000, 100, "alice");
-        let order = TestOrder::new("b1", true, 1000, 100)
-            .with_owner("alice")
-            .with_stp(STP::CancelMaker);
+        setup_order(&mut ob, "s1", false, 1000, 50);
         let mut eval = Evaluator::default();
-        let i: Vec<_> = eval.eval(&ob, Op::Insert(order.clone())).collect();
-        let insert_b1 = i
-            .iter()
-            .any(|p| matches!(p, Instruction::Insert(o, r) if o.id() == "b1" && *r == 100));
-        let cancels: Vec<_> = i
+        let order = TestOrder::new("b1", true, 0, 100)
+            .with_order_type(OrderType::Market)
+            .with_tif(TIF::FOK);
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(order)).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b1"), Msg::FOKNotFilled)]
+        );
+
+        let order = TestOrder::new("b2", true, 0, 100)
+            .with_order_type(OrderType::MarketToLimit)
+            .with_tif(TIF::IOC);
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(order)).collect();
+        assert_eq!(
+            i.last(),
+            Some(&Instruction::Delete(String::from("b2"), Msg::IOCLeftover))
+        );
+    }
+
+    #[test]
+    fn test_market_to_limit_rests_at_last_fill() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 30);
+        setup_order(&mut ob, "s2", false, 1010, 30);
+        let order = TestOrder::new("b1", true, 0, 100).with_order_type(OrderType::MarketToLimit);
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(order)).collect();
+        let mut rested =
+            TestOrder::new("b1", true, 1010, 100).with_order_type(OrderType::MarketToLimit);
+        rested.amend(1010, 100);
+        assert_eq!(i.last(), Some(&Instruction::Insert(rested, 40)));
+        for instr in i {
+            ob.apply(instr);
+        }
+        assert!(ob.asks.is_empty());
+        assert_eq!(ob.best_bid(), Some((1010, 40)));
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Stop order tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_stop_held_until_triggered() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        let stop = TestOrder::new("st1", true, 0, 10)
+            .with_order_type(OrderType::Market)
+            .with_stop_price(1010);
+        let i = drive(&mut ob, Op::Insert(stop.clone()));
+        assert_eq!(i, vec![Instruction::Hold(stop)]);
+        assert_eq!(ob.stop_count(), 1);
+        assert!(ob.is_empty());
+        assert!(ob.best_bid().is_none());
+        assert_eq!(
+            ob.stop(&String::from("st1")).unwrap().stop_price(),
+            Some(1010)
+        );
+
+        // Duplicate of a held stop is rejected; it can be cancelled.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("st1", true, 1000, 10)));
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(
+                String::from("st1"),
+                Msg::OrderAlreadyExists
+            )]
+        );
+        let i = drive(&mut ob, Op::Delete(String::from("st1")));
+        assert_eq!(
+            i,
+            vec![Instruction::Delete(String::from("st1"), Msg::UserCancelled)]
+        );
+        assert_eq!(ob.stop_count(), 0);
+    }
+
+    #[test]
+    fn test_triggered_stop_cancelled_before_release() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 10);
+        setup_order(&mut ob, "s2", false, 1010, 10);
+        let stop = TestOrder::new("st1", true, 1010, 10).with_stop_price(1000);
+        drive(&mut ob, Op::Insert(stop));
+
+        let mut eval = Evaluator::default();
+        for instr in eval.eval(&ob, Op::Insert(TestOrder::new("b1", true, 1000, 10))) {
+            ob.apply(instr);
+        }
+        assert_eq!(ob.triggered.len(), 1);
+        // Triggered but not yet released: its id is taken and it can be cancelled.
+        eval.reset();
+        let i: Vec<_> = eval
+            .eval(&ob, Op::Insert(TestOrder::new("st1", true, 990, 10)))
+            .collect();
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(
+                String::from("st1"),
+                Msg::OrderAlreadyExists
+            )]
+        );
+        let i = drive(&mut ob, Op::Delete(String::from("st1")));
+        assert_eq!(
+            i,
+            vec![Instruction::Delete(String::from("st1"), Msg::UserCancelled)]
+        );
+        assert!(ob.triggered.is_empty());
+        assert_eq!(ob.best_ask(), Some((1010, 10)));
+
+        // A released order reusing a live id is rejected.
+        setup_order(&mut ob, "b2", true, 990, 10);
+        let i = drive(&mut ob, Op::Trigger(TestOrder::new("b2", true, 990, 10)));
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(
+                String::from("b2"),
+                Msg::OrderAlreadyExists
+            )]
+        );
+        drive(&mut ob, Op::Delete(String::from("b2")));
+        assert_eq!(ob.best_bid(), None);
+    }
+
+    #[test]
+    fn test_stop_market_triggered_by_trade() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 10);
+        setup_order(&mut ob, "s2", false, 1010, 10);
+        setup_order(&mut ob, "s3", false, 1020, 10);
+        let stop = TestOrder::new("st1", true, 0, 15)
+            .with_order_type(OrderType::Market)
+            .with_stop_price(1000);
+        drive(&mut ob, Op::Insert(stop));
+
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 10)));
+        assert_eq!(ob.last_trade_price(), Some(1020));
+        assert_eq!(
+            &i[2..],
+            &[
+                Instruction::Fill(String::from("st1"), String::from("st1"), 1013, 15, true),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1010, 10, false),
+                Instruction::Fill(String::from("s3"), String::from("s3"), 1020, 5, false),
+            ]
+        );
+        assert_eq!(ob.stop_count(), 0);
+        assert_eq!(ob.best_ask(), Some((1020, 5)));
+    }
+
+    #[test]
+    fn test_stop_cascade_order() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "b1", true, 1000, 10);
+        setup_order(&mut ob, "b2", true, 990, 10);
+        setup_order(&mut ob, "b3", true, 980, 10);
+        // Both trigger at 1000; st1 first (higher sell stop), whose fill at 990
+        // triggers st3 behind st2.
+        for (id, stop) in [("st2", 1000), ("st1", 1005), ("st3", 990)] {
+            let order = TestOrder::new(id, false, 0, 10)
+                .with_order_type(OrderType::Market)
+                .with_stop_price(stop);
+            drive(&mut ob, Op::Insert(order));
+        }
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("s1", false, 1000, 10)));
+        let takers: Vec<_> = i
+            .iter()
+            .filter_map(|p| match p {
+                Instruction::Fill(id, _, price, _, true) => Some((id.as_str(), *price)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(takers, vec![("s1", 1000), ("st1", 990), ("st2", 980)]);
+        assert_eq!(
+            i.last(),
+            Some(&Instruction::NoOp(String::from("st3"), Msg::MarketNoFill))
+        );
+        assert!(ob.is_empty());
+        assert_eq!(ob.stop_count(), 0);
+    }
+
+    #[test]
+    fn test_stop_limit_rests_after_trigger() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 10);
+        setup_order(&mut ob, "s2", false, 1050, 10);
+        let stop = TestOrder::new("st1", true, 1010, 20).with_stop_price(1000);
+        drive(&mut ob, Op::Insert(stop));
+        drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 10)));
+        assert_eq!(ob.best_bid(), Some((1010, 20)));
+        assert_eq!(ob.best_ask(), Some((1050, 10)));
+    }
+
+    #[test]
+    fn test_stop_triggers_on_arrival() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 10);
+        setup_order(&mut ob, "b1", true, 990, 10);
+        drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1000, 10)));
+        assert_eq!(ob.last_trade_price(), Some(1000));
+        // Sell stop at 1000 is already triggered by the last trade.
+        let stop = TestOrder::new("st1", false, 990, 10).with_stop_price(1000);
+        let i = drive(&mut ob, Op::Insert(stop));
+        assert_eq!(
+            i[1],
+            Instruction::Fill(String::from("b1"), String::from("b1"), 990, 10, false)
+        );
+    }
+
+    #[test]
+    fn test_trailing_stop_follows_best_bid() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "b1", true, 1000, 10);
+        let stop = TestOrder::new("st1", false, 0, 10)
+            .with_order_type(OrderType::Market)
+            .with_trail(Trail {
+                offset: Offset::Fixed(10),
+                reference: TrailRef::Best,
+            });
+        let i = drive(&mut ob, Op::Insert(stop.clone()));
+        assert_eq!(i, vec![Instruction::Hold(stop)]);
+        assert_eq!(ob.stop_trigger(&String::from("st1")), Some(990));
+
+        // Ratchets up with the best bid, never down.
+        drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1020, 5)));
+        assert_eq!(ob.stop_trigger(&String::from("st1")), Some(1010));
+
+        // Best bid falls back to 1000, crossing 1010: the stop sells into b1.
+        let i = drive(&mut ob, Op::Delete(String::from("b2")));
+        assert_eq!(
+            &i[1..],
+            &[
+                Instruction::Fill(String::from("st1"), String::from("st1"), 1000, 10, true),
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 10, false),
+            ]
+        );
+        assert_eq!(ob.stop_count(), 0);
+        assert!(ob.is_empty());
+    }
+
+    #[test]
+    fn test_trailing_stop_follows_last_trade() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        let stop = TestOrder::new("st1", true, 0, 10)
+            .with_order_type(OrderType::Market)
+            .with_trail(Trail {
+                offset: Offset::Ratio(1, 100),
+                reference: TrailRef::LastTrade,
+            });
+        drive(&mut ob, Op::Insert(stop));
+        assert_eq!(ob.stop_trigger(&String::from("st1")), None);
+
+        let trade = |ob: &mut OrderBook<TestOrder>, id: &str, price: u64| {
+            setup_order(ob, id, false, price, 1);
+            drive(ob, Op::Insert(TestOrder::new("t", true, price, 1)));
+        };
+        trade(&mut ob, "s1", 1000);
+        assert_eq!(ob.stop_trigger(&String::from("st1")), Some(1010));
+        trade(&mut ob, "s2", 900);
+        assert_eq!(ob.stop_trigger(&String::from("st1")), Some(909));
+        setup_order(&mut ob, "s3", false, 950, 20);
+        trade(&mut ob, "s4", 910);
+        // Triggered at 910: the stop buys 10 from s3.
+        assert_eq!(ob.stop_count(), 0);
+        assert_eq!(ob.best_ask(), Some((950, 10)));
+        assert_eq!(ob.last_trade_price(), Some(950));
+    }
+
+    #[test]
+    fn test_trailing_stop_cancel() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        let stop = TestOrder::new("st1", false, 0, 10).with_trail(Trail {
+            offset: Offset::Fixed(2000),
+            reference: TrailRef::Best,
+        });
+        drive(&mut ob, Op::Insert(stop));
+        setup_order(&mut ob, "b1", true, 1000, 10);
+        // Offset larger than the reference floors the trigger at zero.
+        drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 900, 10)));
+        assert_eq!(ob.stop_trigger(&String::from("st1")), Some(0));
+        drive(&mut ob, Op::Delete(String::from("st1")));
+        assert_eq!(ob.stop_count(), 0);
+        assert_eq!(ob.stop_trigger(&String::from("st1")), None);
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Iceberg tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_iceberg_displays_slice_only() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 500).with_display_quantity(100),
+        );
+        setup_order(&mut ob, "s2", false, 1010, 40);
+        assert_eq!(ob.best_ask(), Some((1000, 100)));
+        assert_eq!(ob.top_asks(2), vec![(1000, 100), (1010, 40)]);
+        assert_eq!(ob.order(&String::from("s1")).unwrap().remaining(), 500);
+    }
+
+    #[test]
+    fn test_iceberg_refresh_loses_priority() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 250).with_display_quantity(100),
+        );
+        setup_order(&mut ob, "s2", false, 1000, 50);
+        // Takes s1's slice, then s2, then the refreshed slice of s1.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 180)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 180, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 100, false),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1000, 50, false),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 30, false),
+            ]
+        );
+        assert_eq!(ob.best_ask(), Some((1000, 70)));
+        assert_eq!(ob.order(&String::from("s1")).unwrap().remaining(), 120);
+    }
+
+    #[test]
+    fn test_iceberg_refresh_across_batch() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 150).with_display_quantity(100),
+        );
+        setup_order(&mut ob, "s2", false, 1000, 50);
+        let mut eval = Evaluator::default();
+        let first: Vec<_> = eval
+            .eval(&ob, Op::Insert(TestOrder::new("b1", true, 1000, 100)))
+            .collect();
+        // Second taker in the same batch sees s1's refreshed slice of 50.
+        let second: Vec<_> = eval
+            .eval(&ob, Op::Insert(TestOrder::new("b2", true, 1000, 100)))
+            .collect();
+        assert_eq!(
+            second,
+            vec![
+                Instruction::Fill(String::from("b2"), String::from("b2"), 1000, 100, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 50, false),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1000, 50, false),
+            ]
+        );
+        for instr in first.into_iter().chain(second) {
+            ob.apply(instr);
+        }
+        assert!(ob.is_empty());
+    }
+
+    #[test]
+    fn test_iceberg_replace_and_cancel() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 500).with_display_quantity(100),
+        );
+        drive(
+            &mut ob,
+            Op::Replace {
+                id: String::from("s1"),
+                new_price: 1000,
+                new_quantity: 60,
+            },
+        );
+        assert_eq!(ob.best_ask(), Some((1000, 60)));
+        drive(
+            &mut ob,
+            Op::Replace {
+                id: String::from("s1"),
+                new_price: 1010,
+                new_quantity: 300,
+            },
+        );
+        assert_eq!(ob.best_ask(), Some((1010, 100)));
+        drive(&mut ob, Op::Delete(String::from("s1")));
+        assert!(ob.is_empty());
+        assert!(ob.asks.is_empty());
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Peg tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    fn peg(reference: PegRef, offset: u64, aggressive: bool) -> Peg<u64> {
+        Peg {
+            reference,
+            offset,
+            aggressive,
+            cap: None,
+        }
+    }
+
+    #[test]
+    fn test_peg_priced_on_entry() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "b1", true, 1000, 10);
+        setup_order(&mut ob, "s1", false, 1010, 10);
+        let order = TestOrder::new("p1", true, 0, 20).with_peg(peg(PegRef::Primary, 2, false));
+        let i = drive(&mut ob, Op::Insert(order));
+        let mut rested = TestOrder::new("p1", true, 0, 20).with_peg(peg(PegRef::Primary, 2, false));
+        rested.amend(998, 20);
+        assert_eq!(i, vec![Instruction::Insert(rested, 20)]);
+        assert_eq!(ob.peg_count(), 1);
+
+        let order = TestOrder::new("p2", false, 0, 20).with_peg(peg(PegRef::Market, 1, false));
+        drive(&mut ob, Op::Insert(order));
+        assert_eq!(ob.order(&String::from("p2")).unwrap().price(), 1001);
+        assert_eq!(ob.best_ask(), Some((1001, 20)));
+    }
+
+    #[test]
+    fn test_peg_ignores_hidden_reference() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "b1", true, 100, 10);
+        setup(
+            &mut ob,
+            TestOrder::new("b2", true, 105, 10).with_hidden(true),
+        );
+        let order = TestOrder::new("p1", true, 0, 10).with_peg(peg(PegRef::Primary, 0, false));
+        drive(&mut ob, Op::Insert(order));
+        assert_eq!(ob.order(&String::from("p1")).unwrap().price(), 100);
+        assert_eq!(ob.best_bid(), Some((100, 20)));
+    }
+
+    #[test]
+    fn test_peg_no_reference() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        let order = TestOrder::new("p1", true, 0, 20).with_peg(peg(PegRef::Primary, 0, false));
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("p1"), Msg::PegNoReference)]
+        );
+        assert!(ob.is_empty());
+    }
+
+    #[test]
+    fn test_peg_follows_bbo() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "b1", true, 1000, 10);
+        let order = TestOrder::new("p1", true, 0, 20).with_peg(peg(PegRef::Primary, 0, false));
+        drive(&mut ob, Op::Insert(order));
+        assert_eq!(ob.best_bid(), Some((1000, 30)));
+
+        // Better bid arrives: the peg moves up behind it.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1005, 10)));
+        assert_eq!(
+            i[1..],
+            [Instruction::Replace(String::from("p1"), 1005, 20, 20)]
+        );
+        let ids: Vec<_> = ob.bids().next().unwrap().iter().map(|o| o.id()).collect();
+        assert_eq!(ids, vec!["b2", "p1"]);
+
+        // Best bid cancelled: the peg falls back to the next unpegged price.
+        drive(&mut ob, Op::Delete(String::from("b2")));
+        assert_eq!(ob.order(&String::from("p1")).unwrap().price(), 1000);
+        assert_eq!(ob.best_bid(), Some((1000, 30)));
+
+        // Reference gone: the peg stays at its last price.
+        drive(&mut ob, Op::Delete(String::from("b1")));
+        assert_eq!(ob.best_bid(), Some((1000, 20)));
+        drive(&mut ob, Op::Delete(String::from("p1")));
+        assert_eq!(ob.peg_count(), 0);
+    }
+
+    #[test]
+    fn test_peg_reprice_held_while_halted() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "b1", true, 1000, 10);
+        setup_order(&mut ob, "b2", true, 1005, 10);
+        let order = TestOrder::new("p1", true, 0, 20).with_peg(peg(PegRef::Primary, 0, false));
+        drive(&mut ob, Op::Insert(order));
+        assert_eq!(ob.best_bid(), Some((1005, 30)));
+
+        drive(&mut ob, Op::Phase(Phase::Halted));
+        let i = drive(&mut ob, Op::Delete(String::from("b2")));
+        assert_eq!(i.len(), 1);
+        assert_eq!(ob.order(&String::from("p1")).unwrap().price(), 1005);
+
+        let i = drive(&mut ob, Op::Phase(Phase::Continuous));
+        assert_eq!(
+            i[1..],
+            [Instruction::Replace(String::from("p1"), 1000, 20, 20)]
+        );
+        assert_eq!(ob.best_bid(), Some((1000, 30)));
+    }
+
+    #[test]
+    fn test_peg_aggressive_ignores_pegs_and_caps() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "b1", true, 1000, 10);
+        setup_order(&mut ob, "s1", false, 1010, 10);
+        let mut p = peg(PegRef::Primary, 1, true);
+        let order = TestOrder::new("p1", true, 0, 20).with_peg(p);
+        drive(&mut ob, Op::Insert(order));
+        p.cap = Some(1002);
+        let order = TestOrder::new("p2", true, 0, 20).with_peg(p);
+        drive(&mut ob, Op::Insert(order));
+        // Neither peg references the other, so they do not walk up the book.
+        assert_eq!(ob.top_bids(2), vec![(1001, 40), (1000, 10)]);
+
+        drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1004, 10)));
+        assert_eq!(ob.order(&String::from("p1")).unwrap().price(), 1005);
+        assert_eq!(ob.order(&String::from("p2")).unwrap().price(), 1002);
+    }
+
+    #[test]
+    fn test_peg_reprice_can_trade() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1010, 10);
+        setup_order(&mut ob, "s2", false, 1020, 10);
+        // Pegged to the best ask: takes s1, rests, then chases the ask into s2.
+        let order = TestOrder::new("p1", true, 0, 15).with_peg(peg(PegRef::Market, 0, true));
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i[3..],
+            [
+                Instruction::Fill(String::from("p1"), String::from("p1"), 1020, 5, true),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1020, 5, false),
+                Instruction::Replace(String::from("p1"), 1020, 15, 0),
+            ]
+        );
+        assert!(ob.bids.is_empty());
+        assert_eq!(ob.peg_count(), 0);
+        assert_eq!(ob.best_ask(), Some((1020, 5)));
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Expiry and session tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_expire_until() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        let gtt = |id: &str, at: u64| {
+            TestOrder::new(id, true, 1000, 10)
+                .with_tif(TIF::GTT)
+                .with_expire_at(at)
+        };
+        drive(&mut ob, Op::Insert(gtt("b1", 20)));
+        drive(&mut ob, Op::Insert(gtt("b2", 10)));
+        drive(
+            &mut ob,
+            Op::Insert(
+                TestOrder::new("b3", true, 990, 10)
+                    .with_tif(TIF::GTD)
+                    .with_expire_at(20),
+            ),
+        );
+        // Expiry is ignored for GTC.
+        drive(
+            &mut ob,
+            Op::Insert(TestOrder::new("b4", true, 990, 10).with_expire_at(5)),
+        );
+        assert_eq!(ob.expiry_count(), 3);
+        assert!(ob.expire_until(9).is_empty());
+
+        let i = ob.expire_until(20);
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Delete(String::from("b2"), Msg::Expired),
+                Instruction::Delete(String::from("b1"), Msg::Expired),
+                Instruction::Delete(String::from("b3"), Msg::Expired),
+            ]
+        );
+        for instr in i {
+            ob.apply(instr);
+        }
+        assert_eq!(ob.expiry_count(), 0);
+        assert_eq!(ob.len(), 1);
+    }
+
+    #[test]
+    fn test_expiry_cleared_on_fill_and_replace() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        let gtt = |id: &str, qty: u64| {
+            TestOrder::new(id, false, 1000, qty)
+                .with_tif(TIF::GTT)
+                .with_expire_at(10)
+        };
+        drive(&mut ob, Op::Insert(gtt("s1", 10)));
+        drive(&mut ob, Op::Insert(gtt("s2", 10)));
+        drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 15)));
+        assert_eq!(ob.expiry_count(), 1);
+        drive(
+            &mut ob,
+            Op::Replace {
+                id: String::from("s2"),
+                new_price: 1000,
+                new_quantity: 5,
+            },
+        );
+        assert_eq!(ob.expiry_count(), 0);
+        assert!(ob.is_empty());
+    }
+
+    #[test]
+    fn test_expired_on_arrival() {
+        let now = std::rc::Rc::new(std::cell::Cell::new(100));
+        let clock = now.clone();
+        let mut ob = OrderBook::<TestOrder>::with_clock(move || clock.get());
+        assert_eq!(ob.now(), Some(100));
+        let order = TestOrder::new("b1", true, 1000, 10)
+            .with_tif(TIF::GTD)
+            .with_expire_at(100);
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(i, vec![Instruction::NoOp(String::from("b1"), Msg::Expired)]);
+
+        let order = TestOrder::new("b1", true, 1000, 10)
+            .with_tif(TIF::GTD)
+            .with_expire_at(150);
+        drive(&mut ob, Op::Insert(order));
+        now.set(150);
+        let expired = ob.expire_until(ob.now().unwrap());
+        assert_eq!(expired.len(), 1);
+        for instr in expired {
+            ob.apply(instr);
+        }
+        assert!(ob.is_empty());
+    }
+
+    #[test]
+    fn test_stop_expiry() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        let stop = |id: &str, is_buy: bool| {
+            TestOrder::new(id, is_buy, 1100, 10)
+                .with_stop_price(1100)
+                .with_tif(TIF::GTT)
+                .with_expire_at(10)
+        };
+        drive(&mut ob, Op::Insert(stop("st1", true)));
+        drive(&mut ob, Op::Insert(stop("st2", true)));
+        assert_eq!(ob.expiry_count(), 2);
+        drive(&mut ob, Op::Delete(String::from("st2")));
+        assert_eq!(ob.expiry_count(), 1);
+
+        // Triggered into a resting limit: indexed again from the book.
+        setup_order(&mut ob, "s1", false, 1100, 5);
+        drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1100, 5)));
+        assert_eq!(ob.stop_count(), 0);
+        assert_eq!(ob.best_bid(), Some((1100, 10)));
+        assert_eq!(ob.expiry_count(), 1);
+        for instr in ob.expire_until(10) {
+            ob.apply(instr);
+        }
+        assert!(ob.is_empty());
+
+        // Trailing stops leave the index when triggered.
+        let trail = TestOrder::new("st3", false, 0, 10)
+            .with_trail(Trail {
+                offset: Offset::Fixed(50),
+                reference: TrailRef::LastTrade,
+            })
+            .with_order_type(OrderType::Market)
+            .with_tif(TIF::GTT)
+            .with_expire_at(10);
+        drive(&mut ob, Op::Insert(trail));
+        assert_eq!(ob.expiry_count(), 1);
+        setup_order(&mut ob, "b2", true, 1000, 5);
+        drive(&mut ob, Op::Insert(TestOrder::new("s2", false, 1000, 5)));
+        assert_eq!(ob.stop_count(), 0);
+        assert_eq!(ob.expiry_count(), 0);
+    }
+
+    #[test]
+    fn test_end_session_purges_day_orders() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        let day = |id: &str, is_buy: bool, price: u64| {
+            TestOrder::new(id, is_buy, price, 10).with_tif(TIF::Day)
+        };
+        drive(&mut ob, Op::Insert(day("b1", true, 990)));
+        drive(&mut ob, Op::Insert(day("s1", false, 1010)));
+        drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1000, 10)));
+        drive(&mut ob, Op::Insert(day("b3", true, 1000)));
+        drive(
+            &mut ob,
+            Op::Insert(day("st1", true, 1100).with_stop_price(1100)),
+        );
+        let i = ob.end_session();
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Delete(String::from("b1"), Msg::SessionEnd),
+                Instruction::Delete(String::from("s1"), Msg::SessionEnd),
+                Instruction::Delete(String::from("b3"), Msg::SessionEnd),
+                Instruction::Delete(String::from("st1"), Msg::SessionEnd),
+            ]
+        );
+        for instr in i {
+            ob.apply(instr);
+        }
+        assert_eq!(ob.len(), 1);
+        assert_eq!(ob.stop_count(), 0);
+        assert_eq!(ob.day_count(), 0);
+        assert!(ob.end_session().is_empty());
+    }
+
+    #[test]
+    fn test_end_session_purges_held_day_orders() {
+        let mut ob = OrderBook::<TestOrder>::default().with_band(Band {
+            reference: BandRef::LastTrade,
+            width: 10,
+            action: BandAction::Halt,
+        });
+        setup_order(&mut ob, "s1", false, 1000, 10);
+        drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 10)));
+        let stop = TestOrder::new("st1", true, 1010, 5)
+            .with_stop_price(1004)
+            .with_tif(TIF::Day);
+        drive(&mut ob, Op::Insert(stop));
+        setup_order(&mut ob, "s2", false, 1005, 50);
+        setup_order(&mut ob, "s3", false, 1020, 50);
+
+        // Triggered by the sweep that halts the book, and held until it resumes.
+        let order = TestOrder::new("b2", true, 0, 100).with_order_type(OrderType::Market);
+        drive(&mut ob, Op::Insert(order));
+        assert_eq!(ob.phase(), Phase::Halted);
+        let i = ob.end_session();
+        assert_eq!(
+            i,
+            vec![Instruction::Delete(String::from("st1"), Msg::SessionEnd)]
+        );
+        for instr in i {
+            ob.apply(instr);
+        }
+        assert!(!ob.contains(&String::from("st1")));
+        assert!(ob.held.is_empty());
+
+        // A market order held for the call auction.
+        drive(&mut ob, Op::Phase(Phase::PreOpen));
+        let order = TestOrder::new("m1", true, 0, 10)
+            .with_order_type(OrderType::Market)
+            .with_tif(TIF::Day);
+        drive(&mut ob, Op::Insert(order));
+        assert_eq!(ob.auction_count(), 1);
+        let i = ob.end_session();
+        assert_eq!(
+            i,
+            vec![Instruction::Delete(String::from("m1"), Msg::SessionEnd)]
+        );
+        for instr in i {
+            ob.apply(instr);
+        }
+        assert_eq!(ob.auction_count(), 0);
+        assert_eq!(ob.day_count(), 0);
+
+        let i = drive(&mut ob, Op::Phase(Phase::Continuous));
+        assert_eq!(i, vec![Instruction::Phase(Phase::Continuous)]);
+        assert_eq!(ob.best_bid(), None);
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // MinQty tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_min_qty_taker_rejected() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 30);
+        setup_order(&mut ob, "s2", false, 1010, 30);
+        let order = TestOrder::new("b1", true, 1000, 100).with_min_qty(50, false);
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b1"), Msg::MinQtyNotFilled)]
+        );
+        assert_eq!(ob.len(), 2);
+    }
+
+    #[test]
+    fn test_min_qty_taker_fills_and_rests() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 30);
+        setup_order(&mut ob, "s2", false, 1010, 30);
+        let order = TestOrder::new("b1", true, 1010, 100).with_min_qty(50, false);
+        let i = drive(&mut ob, Op::Insert(order.clone()));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1005, 60, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 30, false),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1010, 30, false),
+                Instruction::Insert(order, 40),
+            ]
+        );
+        // The minimum only applies on entry: a small taker can hit the rested remainder.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("s3", false, 1010, 5)));
+        assert_eq!(
+            i[1],
+            Instruction::Fill(String::from("b1"), String::from("b1"), 1010, 5, false)
+        );
+    }
+
+    #[test]
+    fn test_min_qty_resting_maker_skipped() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 100).with_min_qty(50, true),
+        );
+        setup_order(&mut ob, "s2", false, 1000, 40);
+        // Too small for s1: s1 keeps its place and s2 fills.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 20)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 20, true),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1000, 20, false),
+            ]
+        );
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1000, 60)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b2"), String::from("b2"), 1000, 60, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 60, false),
+            ]
+        );
+        // Less than the minimum left: a fill of all of it is accepted.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b3", true, 1000, 40)));
+        assert_eq!(
+            i[1],
+            Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 40, false)
+        );
+        assert_eq!(ob.best_ask(), Some((1000, 20)));
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // All-or-none tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_aon_maker_skipped_until_filled_in_full() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 100).with_all_or_none(true),
+        );
+        setup_order(&mut ob, "s2", false, 1000, 40);
+        assert_eq!(ob.best_ask(), Some((1000, 40)));
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 60)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 40, true),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1000, 40, false),
+                Instruction::Insert(TestOrder::new("b1", true, 1000, 60), 20),
+            ]
+        );
+        drive(&mut ob, Op::Delete(String::from("b1")));
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1000, 150)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b2"), String::from("b2"), 1000, 100, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 100, false),
+                Instruction::Insert(TestOrder::new("b2", true, 1000, 150), 50),
+            ]
+        );
+        assert!(ob.asks.is_empty());
+    }
+
+    #[test]
+    fn test_aon_taker_rests_untouched() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 40);
+        let order = TestOrder::new("b1", true, 1000, 100).with_all_or_none(true);
+        let i = drive(&mut ob, Op::Insert(order.clone()));
+        assert_eq!(i, vec![Instruction::Insert(order, 100)]);
+        assert_eq!(ob.best_bid(), None);
+
+        let order = TestOrder::new("b2", true, 1000, 100)
+            .with_all_or_none(true)
+            .with_tif(TIF::IOC);
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b2"), Msg::IOCNoFill)]
+        );
+    }
+
+    #[test]
+    fn test_aon_and_hidden_ignore_display_quantity() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 50)
+                .with_all_or_none(true)
+                .with_display_quantity(10),
+        );
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 50)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 50, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 50, false),
+            ]
+        );
+
+        setup(
+            &mut ob,
+            TestOrder::new("s2", false, 1000, 50)
+                .with_hidden(true)
+                .with_display_quantity(10),
+        );
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1000, 30)));
+        assert_eq!(
+            i[1],
+            Instruction::Fill(String::from("s2"), String::from("s2"), 1000, 30, false)
+        );
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b3", true, 1000, 30)));
+        assert_eq!(
+            i[1],
+            Instruction::Fill(String::from("s2"), String::from("s2"), 1000, 20, false)
+        );
+        assert!(ob.asks.is_empty());
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Hidden order tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_hidden_not_displayed() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 100).with_hidden(true),
+        );
+        setup(
+            &mut ob,
+            TestOrder::new("s2", false, 1010, 100).with_hidden(true),
+        );
+        setup_order(&mut ob, "s3", false, 1010, 40);
+        setup_order(&mut ob, "s4", false, 1020, 50);
+        assert_eq!(ob.best_ask(), Some((1010, 40)));
+        assert_eq!(ob.top_asks(3), vec![(1010, 40), (1020, 50)]);
+        assert_eq!(ob.ask_depth(), 3);
+        assert_eq!(ob.asks().next().unwrap().hidden_quantity(), 100);
+    }
+
+    #[test]
+    fn test_hidden_ranks_behind_displayed() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 100).with_hidden(true),
+        );
+        setup(
+            &mut ob,
+            TestOrder::new("s2", false, 1000, 60).with_display_quantity(20),
+        );
+        setup_order(&mut ob, "s3", false, 1000, 30);
+        // s2's slice, s3, s2's refreshed slices, then the hidden s1.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 120)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 120, true),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1000, 20, false),
+                Instruction::Fill(String::from("s3"), String::from("s3"), 1000, 30, false),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1000, 20, false),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1000, 20, false),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 30, false),
+            ]
+        );
+        assert_eq!(ob.best_ask(), None);
+        assert_eq!(ob.order(&String::from("s1")).unwrap().remaining(), 70);
+    }
+
+    #[test]
+    fn test_hidden_pro_rata_after_displayed() {
+        let mut ob = OrderBook::<TestOrder>::default()
+            .with_allocation(Allocation::ProRata { top_order: false });
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 100).with_hidden(true),
+        );
+        setup_order(&mut ob, "s2", false, 1000, 30);
+        setup_order(&mut ob, "s3", false, 1000, 30);
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 80)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 80, true),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1000, 30, false),
+                Instruction::Fill(String::from("s3"), String::from("s3"), 1000, 30, false),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 20, false),
+            ]
+        );
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Discretionary order tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_discretion_trades_at_taker_limit() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("b1", true, 990, 50).with_discretion_price(1000),
+        );
+        setup_order(&mut ob, "b2", true, 995, 10);
+        assert_eq!(ob.best_bid(), Some((995, 10)));
+        // b2 crosses at its price, then b1 trades within discretion at 992.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("s1", false, 992, 50)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("s1"), String::from("s1"), 992, 50, true),
+                Instruction::Fill(String::from("b2"), String::from("b2"), 995, 10, false),
+                Instruction::Fill(String::from("b1"), String::from("b1"), 992, 40, false),
+            ]
+        );
+        assert_eq!(ob.last_trade_price(), Some(992));
+        assert_eq!(ob.order(&String::from("b1")).unwrap().remaining(), 10);
+        assert_eq!(ob.best_bid(), Some((990, 10)));
+    }
+
+    #[test]
+    fn test_discretion_limits() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "b0", true, 995, 10);
+        drive(
+            &mut ob,
+            Op::Insert(TestOrder::new("b1", true, 990, 50).with_discretion_price(1000)),
+        );
+        // Outside discretion, or post-only: no trade.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("s1", false, 1001, 40)));
+        assert_eq!(
+            i,
+            vec![Instruction::Insert(
+                TestOrder::new("s1", false, 1001, 40),
+                40
+            )]
+        );
+        let s2 = TestOrder::new("s2", false, 998, 40).with_post_only(true);
+        let i = drive(&mut ob, Op::Insert(s2.clone()));
+        assert_eq!(i, vec![Instruction::Insert(s2, 40)]);
+        // Removing the only discretionary order turns the search off.
+        drive(&mut ob, Op::Delete(String::from("b1")));
+        assert!(!ob.bids.has_discretionary());
+    }
+
+    #[test]
+    fn test_discretion_skips_levels_out_of_reach() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 104, 10);
+        setup(
+            &mut ob,
+            TestOrder::new("s2", false, 105, 10).with_discretion_price(104),
+        );
+        setup_order(&mut ob, "s3", false, 110, 10);
+        setup(
+            &mut ob,
+            TestOrder::new("s4", false, 130, 10).with_discretion_price(103),
+        );
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 103, 15)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 103, 10, true),
+                Instruction::Fill(String::from("s4"), String::from("s4"), 103, 10, false),
+                Instruction::Insert(TestOrder::new("b1", true, 103, 15), 5),
+            ]
+        );
+        assert!(ob.asks.has_discretionary());
+        drive(&mut ob, Op::Delete(String::from("s2")));
+        assert!(!ob.asks.has_discretionary());
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Pro-rata tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_pro_rata_across_levels() {
+        let mut ob = OrderBook::<TestOrder>::default()
+            .with_allocation(Allocation::ProRata { top_order: false });
+        setup_order(&mut ob, "s1", false, 1000, 30);
+        setup_order(&mut ob, "s2", false, 1000, 60);
+        setup_order(&mut ob, "s3", false, 1010, 100);
+        setup_order(&mut ob, "s4", false, 1010, 100);
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1010, 101)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1001, 101, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 30, false),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1000, 60, false),
+                Instruction::Fill(String::from("s3"), String::from("s3"), 1010, 6, false),
+                Instruction::Fill(String::from("s4"), String::from("s4"), 1010, 5, false),
+            ]
+        );
+        assert_eq!(ob.best_ask(), Some((1010, 189)));
+    }
+
+    #[test]
+    fn test_pro_rata_top_order_and_aon() {
+        let mut ob = OrderBook::<TestOrder>::default()
+            .with_allocation(Allocation::ProRata { top_order: true });
+        setup_order(&mut ob, "s1", false, 1000, 20);
+        setup(
+            &mut ob,
+            TestOrder::new("s2", false, 1000, 50).with_all_or_none(true),
+        );
+        setup_order(&mut ob, "s3", false, 1000, 100);
+        // s1 fills first in full; s2 would get 20 of 50 and drops out, s3 takes the rest.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 90)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 90, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 20, false),
+                Instruction::Fill(String::from("s3"), String::from("s3"), 1000, 70, false),
+            ]
+        );
+        assert_eq!(ob.order(&String::from("s2")).unwrap().remaining(), 50);
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Lead market maker tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    fn lead_makers(share: (u64, u64)) -> LeadMakers<TestOrder> {
+        LeadMakers {
+            owners: vec![String::from("mm")],
+            share,
+        }
+    }
+
+    #[test]
+    fn test_lead_maker_entitlement_ahead_of_fifo() {
+        let mut ob = OrderBook::<TestOrder>::default().with_lead_makers(lead_makers((2, 5)));
+        setup_order_with_owner(&mut ob, "s1", false, 1000, 100, "alice");
+        setup_order_with_owner(&mut ob, "s2", false, 1000, 100, "mm");
+        setup_order_with_owner(&mut ob, "s3", false, 1010, 100, "mm");
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 100)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 100, true),
+                Instruction::Fill(String::from("s2"), String::from("mm"), 1000, 40, false),
+                Instruction::Fill(String::from("s1"), String::from("alice"), 1000, 60, false),
+            ]
+        );
+        // Entitled again at the best level, but not past it.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1010, 150)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b2"), String::from("b2"), 1003, 150, true),
+                Instruction::Fill(String::from("s2"), String::from("mm"), 1000, 60, false),
+                Instruction::Fill(String::from("s1"), String::from("alice"), 1000, 40, false),
+                Instruction::Fill(String::from("s3"), String::from("mm"), 1010, 50, false),
+            ]
+        );
+        assert_eq!(ob.best_ask(), Some((1010, 50)));
+    }
+
+    #[test]
+    fn test_lead_maker_iceberg_refresh_requeued() {
+        let mut ob = OrderBook::<TestOrder>::default().with_lead_makers(lead_makers((1, 2)));
+        setup_order_with_owner(&mut ob, "s1", false, 1000, 100, "alice");
+        setup(
+            &mut ob,
+            TestOrder::new("s2", false, 1000, 100)
+                .with_owner("mm")
+                .with_display_quantity(20),
+        );
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 130)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 130, true),
+                Instruction::Fill(String::from("s2"), String::from("mm"), 1000, 20, false),
+                Instruction::Fill(String::from("s1"), String::from("alice"), 1000, 100, false),
+                Instruction::Fill(String::from("s2"), String::from("mm"), 1000, 10, false),
+            ]
+        );
+        assert_eq!(ob.best_ask(), Some((1000, 10)));
+    }
+
+    #[test]
+    #[should_panic(expected = "lead maker share must be a fraction of at most one")]
+    fn test_lead_maker_zero_denominator() {
+        let _ = OrderBook::<TestOrder>::default().with_lead_makers(lead_makers((1, 0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "lead maker share must be a fraction of at most one")]
+    fn test_lead_maker_share_above_one() {
+        let _ = OrderBook::<TestOrder>::default().with_lead_makers(lead_makers((3, 2)));
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Price collar tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    fn collar(reference: CollarRef, width: u64, action: CollarAction) -> Collar<u64> {
+        Collar {
+            reference,
+            width,
+            action,
+        }
+    }
+
+    #[test]
+    fn test_collar_stops_market_sweep() {
+        let mut ob = OrderBook::<TestOrder>::default().with_collar(collar(
+            CollarRef::Best,
+            10,
+            CollarAction::Cancel,
+        ));
+        setup_order(&mut ob, "s1", false, 1000, 50);
+        setup_order(&mut ob, "s2", false, 1005, 50);
+        setup_order(&mut ob, "s3", false, 1020, 50);
+        let order = TestOrder::new("b1", true, 0, 200).with_order_type(OrderType::Market);
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1002, 100, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 50, false),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1005, 50, false),
+                Instruction::Delete(String::from("b1"), Msg::PriceCollar),
+            ]
+        );
+        assert_eq!(ob.best_ask(), Some((1020, 50)));
+    }
+
+    #[test]
+    fn test_collar_rests_remainder_at_collar() {
+        let mut ob = OrderBook::<TestOrder>::default().with_collar(collar(
+            CollarRef::LastTrade,
+            20,
+            CollarAction::Rest,
+        ));
+        setup_order(&mut ob, "s1", false, 990, 10);
+        drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 990, 10)));
+        assert_eq!(ob.last_trade_price(), Some(990));
+
+        setup_order(&mut ob, "s2", false, 1000, 50);
+        setup_order(&mut ob, "s3", false, 1030, 50);
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1050, 100)));
+        assert_eq!(i.len(), 3);
+        assert_eq!(
+            i[0],
+            Instruction::Fill(String::from("b2"), String::from("b2"), 1000, 50, true)
+        );
+        assert_eq!(ob.best_bid(), Some((1010, 50)));
+        assert_eq!(ob.best_ask(), Some((1030, 50)));
+        assert_eq!(ob.order(&String::from("b2")).unwrap().price(), 1010);
+    }
+
+    #[test]
+    fn test_collar_no_fill_and_limit_inside() {
+        let mut ob = OrderBook::<TestOrder>::default().with_collar(collar(
+            CollarRef::LastTrade,
+            5,
+            CollarAction::Cancel,
+        ));
+        setup_order(&mut ob, "s1", false, 990, 10);
+        drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 990, 10)));
+        setup_order(&mut ob, "s2", false, 1000, 50);
+
+        let order = TestOrder::new("b2", true, 0, 10).with_order_type(OrderType::Market);
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b2"), Msg::PriceCollar)]
+        );
+        // A limit inside the collar rests as usual.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b3", true, 994, 10)));
+        assert_eq!(i.len(), 1);
+        assert_eq!(ob.best_bid(), Some((994, 10)));
+    }
+
+    #[test]
+    fn test_collar_stops_replace() {
+        for action in [CollarAction::Cancel, CollarAction::Rest] {
+            let mut ob =
+                OrderBook::<TestOrder>::default().with_collar(collar(CollarRef::Best, 5, action));
+            for (id, price) in [("s1", 100), ("s2", 105), ("s3", 110), ("s4", 120)] {
+                setup_order(&mut ob, id, false, price, 10);
+            }
+            setup_order(&mut ob, "b1", true, 90, 40);
+            let i = drive(
+                &mut ob,
+                Op::Replace {
+                    id: String::from("b1"),
+                    new_price: 200,
+                    new_quantity: 40,
+                },
+            );
+            assert_eq!(
+                i[..3],
+                [
+                    Instruction::Fill(String::from("b1"), String::from("b1"), 102, 20, true),
+                    Instruction::Fill(String::from("s1"), String::from("s1"), 100, 10, false),
+                    Instruction::Fill(String::from("s2"), String::from("s2"), 105, 10, false),
+                ]
+            );
+            let last = match action {
+                CollarAction::Cancel => Instruction::Delete(String::from("b1"), Msg::PriceCollar),
+                CollarAction::Rest => Instruction::Replace(String::from("b1"), 105, 40, 20),
+            };
+            assert_eq!(i[3..], [last]);
+            assert_eq!(ob.best_ask(), Some((110, 10)));
+        }
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Price band tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_static_band_rejects_remainder() {
+        let mut ob = OrderBook::<TestOrder>::default().with_band(Band {
+            reference: BandRef::Static(1000),
+            width: 10,
+            action: BandAction::Reject,
+        });
+        setup_order(&mut ob, "s1", false, 1005, 50);
+        setup_order(&mut ob, "s2", false, 1015, 50);
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1020, 100)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1005, 50, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1005, 50, false),
+                Instruction::Delete(String::from("b1"), Msg::PriceBand),
+            ]
+        );
+        assert_eq!(ob.phase(), Phase::Continuous);
+        // A limit inside the band rests as usual.
+        drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1008, 10)));
+        assert_eq!(ob.best_bid(), Some((1008, 10)));
+    }
+
+    #[test]
+    fn test_dynamic_band_halts_until_resumed() {
+        let mut ob = OrderBook::<TestOrder>::default().with_band(Band {
+            reference: BandRef::LastTrade,
+            width: 10,
+            action: BandAction::Halt,
+        });
+        setup_order(&mut ob, "s1", false, 1000, 10);
+        drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 10)));
+        setup_order(&mut ob, "s2", false, 1005, 50);
+        setup_order(&mut ob, "s3", false, 1020, 50);
+
+        let order = TestOrder::new("b2", true, 0, 100).with_order_type(OrderType::Market);
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b2"), String::from("b2"), 1005, 50, true),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1005, 50, false),
+                Instruction::Delete(String::from("b2"), Msg::PriceBand),
+                Instruction::Phase(Phase::Halted),
+            ]
+        );
+        assert_eq!(ob.phase(), Phase::Halted);
+
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b3", true, 1020, 10)));
+        assert_eq!(i, vec![Instruction::NoOp(String::from("b3"), Msg::Halted)]);
+        // Cancels still go through.
+        let i = drive(&mut ob, Op::Delete(String::from("s3")));
+        assert_eq!(
+            i,
+            vec![Instruction::Delete(String::from("s3"), Msg::UserCancelled)]
+        );
+
+        drive(&mut ob, Op::Phase(Phase::Continuous));
+        setup_order(&mut ob, "s4", false, 1010, 10);
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b4", true, 1010, 10)));
+        assert_eq!(
+            i[0],
+            Instruction::Fill(String::from("b4"), String::from("b4"), 1010, 10, true)
+        );
+    }
+
+    #[test]
+    fn test_halt_holds_triggered_stops_until_resumed() {
+        let mut ob = OrderBook::<TestOrder>::default().with_band(Band {
+            reference: BandRef::LastTrade,
+            width: 10,
+            action: BandAction::Halt,
+        });
+        setup_order(&mut ob, "s1", false, 1000, 10);
+        drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 10)));
+        let stop = TestOrder::new("st1", true, 1010, 5).with_stop_price(1004);
+        drive(&mut ob, Op::Insert(stop));
+        setup_order(&mut ob, "s2", false, 1005, 50);
+        setup_order(&mut ob, "s3", false, 1020, 50);
+
+        // The fill at 1005 triggers the stop, the sweep on to 1020 halts the book.
+        let order = TestOrder::new("b2", true, 0, 100).with_order_type(OrderType::Market);
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(i.last(), Some(&Instruction::Phase(Phase::Halted)));
+        assert!(ob.contains(&String::from("st1")));
+        assert_eq!(ob.best_bid(), None);
+
+        let i = drive(&mut ob, Op::Phase(Phase::Continuous));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Phase(Phase::Continuous),
+                Instruction::Insert(
+                    TestOrder::new("st1", true, 1010, 5).with_stop_price(1004),
+                    5
+                ),
+            ]
+        );
+        assert_eq!(ob.best_bid(), Some((1010, 5)));
+    }
+
+    #[test]
+    fn test_band_stops_replace() {
+        let mut ob = OrderBook::<TestOrder>::default().with_band(Band {
+            reference: BandRef::Static(1000),
+            width: 10,
+            action: BandAction::Reject,
+        });
+        setup_order(&mut ob, "s1", false, 1020, 50);
+        setup_order(&mut ob, "b1", true, 990, 50);
+        let i = drive(
+            &mut ob,
+            Op::Replace {
+                id: String::from("b1"),
+                new_price: 1020,
+                new_quantity: 50,
+            },
+        );
+        assert_eq!(
+            i,
+            vec![Instruction::Delete(String::from("b1"), Msg::PriceBand)]
+        );
+        assert!(ob.order(&String::from("b1")).is_none());
+    }
+
+    #[test]
+    fn test_band_and_collar_skip_levels_out_of_reach() {
+        let mut ob = OrderBook::<TestOrder>::default().with_band(Band {
+            reference: BandRef::Static(90),
+            width: 5,
+            action: BandAction::Halt,
+        });
+        setup_order(&mut ob, "s1", false, 100, 10);
+        setup(
+            &mut ob,
+            TestOrder::new("s2", false, 120, 10).with_discretion_price(119),
+        );
+        // Neither ask can trade with a buy at 99, so the band is never reached.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 99, 10)));
+        assert_eq!(
+            i,
+            vec![Instruction::Insert(TestOrder::new("b1", true, 99, 10), 10)]
+        );
+        assert_eq!(ob.phase(), Phase::Continuous);
+
+        let mut ob = OrderBook::<TestOrder>::default().with_collar(collar(
+            CollarRef::LastTrade,
+            5,
+            CollarAction::Cancel,
+        ));
+        setup_order(&mut ob, "s0", false, 90, 10);
+        drive(&mut ob, Op::Insert(TestOrder::new("b0", true, 90, 10)));
+        setup_order(&mut ob, "s1", false, 100, 10);
+        setup(
+            &mut ob,
+            TestOrder::new("s2", false, 120, 10).with_discretion_price(119),
+        );
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 99, 10)));
+        assert_eq!(
+            i,
+            vec![Instruction::Insert(TestOrder::new("b1", true, 99, 10), 10)]
+        );
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Call auction tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_auction_accumulates_and_uncrosses() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        drive(&mut ob, Op::Phase(Phase::Auction));
+        let i = drive(
+            &mut ob,
+            Op::Insert(TestOrder::new("s1", false, 1000, 80).with_display_quantity(30)),
+        );
+        assert_eq!(i.len(), 1);
+        setup_order(&mut ob, "s2", false, 1008, 50);
+        setup_order(&mut ob, "b2", true, 1005, 50);
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1010, 100)));
+        assert!(matches!(i[..], [Instruction::Insert(_, 100)]));
+        let i = drive(
+            &mut ob,
+            Op::Insert(TestOrder::new("b3", true, 1010, 10).with_tif(TIF::IOC)),
+        );
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b3"), Msg::NotInAuction)]
+        );
+
+        // Volume peaks at 100 at 1008 and 1010, both with a sell surplus: lowest wins.
+        assert_eq!(ob.indicative(), Some((1008, 100)));
+        let i = ob.uncross();
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1008, 100, false),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1008, 30, false),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1008, 30, false),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1008, 20, false),
+                Instruction::Fill(String::from("s2"), String::from("s2"), 1008, 20, false),
+            ]
+        );
+        for instr in i {
+            ob.apply(instr);
+        }
+        let i = drive(&mut ob, Op::Phase(Phase::Continuous));
+        assert_eq!(i, vec![Instruction::Phase(Phase::Continuous)]);
+        assert_eq!(ob.best_bid(), Some((1005, 50)));
+        assert_eq!(ob.best_ask(), Some((1008, 30)));
+        assert_eq!(ob.last_trade_price(), Some(1008));
+        assert_eq!(ob.indicative(), None);
+    }
+
+    #[test]
+    fn test_auction_market_orders() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        drive(&mut ob, Op::Phase(Phase::Auction));
+        let order = TestOrder::new("m1", true, 0, 60).with_order_type(OrderType::Market);
+        let i = drive(&mut ob, Op::Insert(order.clone()));
+        assert_eq!(i, vec![Instruction::Auction(order)]);
+        assert_eq!(ob.auction_count(), 1);
+        assert_eq!(
+            ob.auction_order(&String::from("m1")).unwrap().remaining(),
+            60
+        );
+        assert_eq!(ob.indicative(), None);
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("m1", true, 990, 10)));
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(
+                String::from("m1"),
+                Msg::OrderAlreadyExists
+            )]
+        );
+
+        setup_order(&mut ob, "b1", true, 999, 10);
+        setup_order(&mut ob, "s1", false, 1000, 50);
+        setup_order(&mut ob, "s2", false, 1002, 50);
+        let order = TestOrder::new("m2", false, 0, 30).with_order_type(OrderType::Market);
+        drive(&mut ob, Op::Insert(order));
+        // 60 trades at 1000 and 1002; 1000 leaves the smaller surplus.
+        assert_eq!(ob.indicative(), Some((1000, 60)));
+        let i = ob.uncross();
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("m1"), String::from("m1"), 1000, 60, false),
+                Instruction::Fill(String::from("m2"), String::from("m2"), 1000, 30, false),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 30, false),
+            ]
+        );
+        for instr in i {
+            ob.apply(instr);
+        }
+        assert_eq!(ob.auction_count(), 0);
+
+        // Unmatched market orders are cancelled at the uncross.
+        let order = TestOrder::new("m3", false, 0, 30).with_order_type(OrderType::Market);
+        drive(&mut ob, Op::Insert(order));
+        let order = TestOrder::new("m4", false, 0, 30).with_order_type(OrderType::Market);
+        drive(&mut ob, Op::Insert(order));
+        let i = drive(&mut ob, Op::Delete(String::from("m4")));
+        assert_eq!(
+            i,
+            vec![Instruction::Delete(String::from("m4"), Msg::UserCancelled)]
+        );
+        let i = ob.uncross();
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 999, 10, false),
+                Instruction::Fill(String::from("m3"), String::from("m3"), 999, 10, false),
+                Instruction::Delete(String::from("m3"), Msg::MarketLeftover),
+            ]
+        );
+        for instr in i {
+            ob.apply(instr);
+        }
+        assert_eq!(ob.auction_count(), 0);
+        assert!(ob.held.is_empty());
+    }
+
+    #[test]
+    fn test_auction_reference_price_tie_break() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s0", false, 1004, 1);
+        drive(&mut ob, Op::Insert(TestOrder::new("b0", true, 1004, 1)));
+        drive(&mut ob, Op::Phase(Phase::Auction));
+        setup_order(&mut ob, "s1", false, 1000, 50);
+        setup_order(&mut ob, "b1", true, 1005, 50);
+        // 50 trades at 1000 or 1005 with no surplus: closest to the last trade wins.
+        assert_eq!(ob.indicative(), Some((1005, 50)));
+
+        let mut ob = OrderBook::<TestOrder>::default();
+        drive(&mut ob, Op::Phase(Phase::Auction));
+        setup_order(&mut ob, "s1", false, 1000, 50);
+        setup_order(&mut ob, "b1", true, 1005, 50);
+        assert_eq!(ob.indicative(), Some((1000, 50)));
+    }
+
+    #[test]
+    #[should_panic(expected = "batch auction interval must be non-zero")]
+    fn test_batch_auction_zero_interval() {
+        let _ = OrderBook::<TestOrder>::with_clock(|| 0).with_batch_auction(0);
+    }
+
+    #[test]
+    fn test_batch_auction_uncrosses_each_interval() {
+        let now = std::rc::Rc::new(std::cell::Cell::new(101));
+        let clock = now.clone();
+        let mut ob = OrderBook::<TestOrder>::with_clock(move || clock.get()).with_batch_auction(10);
+        assert_eq!(ob.batch_interval(), Some(10));
+        setup_order(&mut ob, "s1", false, 1000, 50);
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1005, 30)));
+        assert!(matches!(i[..], [Instruction::Insert(_, 30)]));
+        let i = drive(
+            &mut ob,
+            Op::Insert(TestOrder::new("b2", true, 1005, 10).with_tif(TIF::IOC)),
+        );
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b2"), Msg::NotInAuction)]
+        );
+        assert_eq!(ob.indicative(), Some((1000, 30)));
+        assert!(ob.pop_pending().is_none());
+
+        // The batch ends at 110; the next op is followed by the uncross.
+        now.set(110);
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b3", true, 990, 10)));
+        assert_eq!(
+            i[1..],
+            [
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 30, false),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 30, false),
+            ]
+        );
+        assert_eq!(ob.best_bid(), Some((990, 10)));
+        assert_eq!(ob.best_ask(), Some((1000, 20)));
+        assert!(ob.pop_pending().is_none());
+
+        // One uncross per ended batch, however late it is noticed.
+        now.set(135);
+        assert!(matches!(ob.pop_pending(), Some(Op::Uncross)));
+        assert!(ob.pop_pending().is_none());
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Speed bump tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_speed_bump_delays_takers_only() {
+        let now = std::rc::Rc::new(std::cell::Cell::new(100));
+        let clock = now.clone();
+        let mut ob = OrderBook::<TestOrder>::with_clock(move || clock.get()).with_speed_bump(5);
+        assert_eq!(ob.speed_bump(), Some(5));
+        setup_order(&mut ob, "s1", false, 1000, 50);
+
+        let b1 = TestOrder::new("b1", true, 1000, 20);
+        let i = drive(&mut ob, Op::Insert(b1.clone()));
+        assert_eq!(i, vec![Instruction::Delay(b1, 105)]);
+        assert_eq!(ob.delayed_count(), 1);
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 990, 10)));
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(
+                String::from("b1"),
+                Msg::OrderAlreadyExists
+            )]
+        );
+
+        // Passive orders and cancels go through at once.
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 990, 10)));
+        assert!(matches!(i[..], [Instruction::Insert(_, 10)]));
+        now.set(103);
+        let i = drive(&mut ob, Op::Delete(String::from("s1")));
+        assert_eq!(
+            i,
+            vec![Instruction::Delete(String::from("s1"), Msg::UserCancelled)]
+        );
+
+        // Released at 105 against the book as it is then.
+        now.set(104);
+        assert!(ob.pop_pending().is_none());
+        now.set(105);
+        let i = drive(&mut ob, Op::Delete(String::from("b2")));
+        assert_eq!(i.len(), 2);
+        assert!(matches!(i[1], Instruction::Insert(_, 20)));
+        assert_eq!(ob.delayed_count(), 0);
+        assert!(ob.held.is_empty());
+        assert_eq!(ob.best_bid(), Some((1000, 20)));
+    }
+
+    #[test]
+    fn test_speed_bump_cancel_while_delayed() {
+        let now = std::rc::Rc::new(std::cell::Cell::new(100));
+        let clock = now.clone();
+        let mut ob = OrderBook::<TestOrder>::with_clock(move || clock.get()).with_speed_bump(5);
+        setup_order(&mut ob, "b1", true, 1000, 50);
+        let order = TestOrder::new("s1", false, 0, 20).with_order_type(OrderType::Market);
+        drive(&mut ob, Op::Insert(order));
+        let i = drive(&mut ob, Op::Delete(String::from("s1")));
+        assert_eq!(
+            i,
+            vec![Instruction::Delete(String::from("s1"), Msg::UserCancelled)]
+        );
+        assert_eq!(ob.delayed_count(), 0);
+        assert!(ob.held.is_empty());
+        now.set(105);
+        assert!(ob.pop_pending().is_none());
+        assert_eq!(ob.best_bid(), Some((1000, 50)));
+    }
+
+    #[test]
+    fn test_speed_bump_refuses_marketable_replace() {
+        let now = std::rc::Rc::new(std::cell::Cell::new(100));
+        let clock = now.clone();
+        let mut ob = OrderBook::<TestOrder>::with_clock(move || clock.get()).with_speed_bump(5);
+        setup_order(&mut ob, "s1", false, 100, 10);
+        setup_order(&mut ob, "b1", true, 90, 10);
+        let replace = |new_price| Op::Replace {
+            id: String::from("b1"),
+            new_price,
+            new_quantity: 10,
+        };
+        let i = drive(&mut ob, replace(100));
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b1"), Msg::SpeedBump)]
+        );
+        assert_eq!(ob.best_bid(), Some((90, 10)));
+        let i = drive(&mut ob, replace(95));
+        assert_eq!(
+            i,
+            vec![Instruction::Replace(String::from("b1"), 95, 10, 10)]
+        );
+    }
+
+    #[test]
+    fn test_speed_bump_expiry_and_session_end() {
+        let now = std::rc::Rc::new(std::cell::Cell::new(10));
+        let clock = now.clone();
+        let mut ob = OrderBook::<TestOrder>::with_clock(move || clock.get()).with_speed_bump(5);
+        setup_order(&mut ob, "s1", false, 100, 50);
+        let gtt = TestOrder::new("b1", true, 100, 10)
+            .with_tif(TIF::GTT)
+            .with_expire_at(15);
+        drive(&mut ob, Op::Insert(gtt));
+        let day = TestOrder::new("b2", true, 100, 10).with_tif(TIF::Day);
+        drive(&mut ob, Op::Insert(day));
+        assert_eq!(ob.delayed_count(), 2);
+        assert_eq!(ob.expiry_count(), 1);
+        assert_eq!(
+            ob.expire_until(15),
+            vec![Instruction::Delete(String::from("b1"), Msg::Expired)]
+        );
+        assert_eq!(
+            ob.end_session(),
+            vec![Instruction::Delete(String::from("b2"), Msg::SessionEnd)]
+        );
+
+        // Released after its expiry without being purged: it does not trade.
+        now.set(40);
+        let i = drive(&mut ob, Op::Delete(String::from("b2")));
+        assert_eq!(i[1], Instruction::NoOp(String::from("b1"), Msg::Expired));
+        assert_eq!(ob.delayed_count(), 0);
+        assert_eq!(ob.expiry_count(), 0);
+        assert_eq!(ob.best_ask(), Some((100, 50)));
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Phase tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_phase_closed_refuses_inserts() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 50);
+        let i = drive(&mut ob, Op::Phase(Phase::Closed));
+        assert_eq!(i, vec![Instruction::Phase(Phase::Closed)]);
+        assert_eq!(ob.phase(), Phase::Closed);
+        assert!(drive(&mut ob, Op::Phase(Phase::Closed)).is_empty());
+
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b1", true, 1000, 10)));
+        assert_eq!(i, vec![Instruction::NoOp(String::from("b1"), Msg::Closed)]);
+        let i = drive(
+            &mut ob,
+            Op::Replace {
+                id: String::from("s1"),
+                new_price: 1001,
+                new_quantity: 50,
+            },
+        );
+        assert_eq!(i, vec![Instruction::NoOp(String::from("s1"), Msg::Closed)]);
+        let i = drive(&mut ob, Op::Delete(String::from("s1")));
+        assert_eq!(
+            i,
+            vec![Instruction::Delete(String::from("s1"), Msg::UserCancelled)]
+        );
+    }
+
+    #[test]
+    fn test_phase_leaving_pre_open_uncrosses() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        drive(&mut ob, Op::Phase(Phase::PreOpen));
+        setup_order(&mut ob, "s1", false, 1000, 50);
+        setup_order(&mut ob, "b1", true, 1000, 30);
+        let order = TestOrder::new("m1", true, 0, 10).with_order_type(OrderType::Market);
+        drive(&mut ob, Op::Insert(order));
+        assert_eq!(ob.indicative(), Some((1000, 40)));
+
+        // PreOpen to Auction is still a call phase: no uncross.
+        let i = drive(&mut ob, Op::Phase(Phase::Auction));
+        assert_eq!(i, vec![Instruction::Phase(Phase::Auction)]);
+
+        let i = drive(&mut ob, Op::Phase(Phase::Continuous));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("m1"), String::from("m1"), 1000, 10, false),
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 30, false),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 40, false),
+                Instruction::Phase(Phase::Continuous),
+            ]
+        );
+        assert_eq!(ob.best_ask(), Some((1000, 10)));
+        assert_eq!(ob.indicative(), None);
+        let i = drive(&mut ob, Op::Insert(TestOrder::new("b2", true, 1000, 10)));
+        assert_eq!(
+            i[0],
+            Instruction::Fill(String::from("b2"), String::from("b2"), 1000, 10, true)
+        );
+    }
+
+    #[test]
+    fn test_phase_change_seen_by_later_ops_in_batch() {
+        let ob = OrderBook::<TestOrder>::default();
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, Op::Phase(Phase::Halted)).collect();
+        assert_eq!(i, vec![Instruction::Phase(Phase::Halted)]);
+        let i: Vec<_> = eval
+            .eval(&ob, Op::Insert(TestOrder::new("b1", true, 1000, 10)))
+            .collect();
+        assert_eq!(i, vec![Instruction::NoOp(String::from("b1"), Msg::Halted)]);
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // STP (self-trade prevention) tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_stp_cancel_taker() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order_with_owner(&mut ob, "s1", false, 1000, 100, "alice");
+        let order = TestOrder::new("b1", true, 1000, 100)
+            .with_owner("alice")
+            .with_stp(STP::CancelTaker);
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(order)).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b1"), Msg::StpCancelTaker)]
+        );
+    }
+
+    #[test]
+    fn test_stp_cancel_taker_no_self_trade() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order_with_owner(&mut ob, "s1", false, 1000, 100, "alice");
+        let order = TestOrder::new("b1", true, 1000, 100)
+            .with_owner("bob")
+            .with_stp(STP::CancelTaker);
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(order.clone())).collect();
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("bob"), 1000, 100, true),
+                Instruction::Fill(String::from("s1"), String::from("alice"), 1000, 100, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stp_cancel_maker() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order_with_owner(&mut ob, "s1", false, 1000, 100, "alice");
+        let order = TestOrder::new("b1", true, 1000, 100)
+            .with_owner("alice")
+            .with_stp(STP::CancelMaker);
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(order.clone())).collect();
+        let insert_b1 = i
+            .iter()
+            .any(|p| matches!(p, Instruction::Insert(o, r) if o.id() == "b1" && *r == 100));
+        let cancels: Vec<_> = i
+            .iter()
+            .filter_map(|p| match p {
+                Instruction::Delete(id, _) => Some(id.clone()),
+                _ => None,
+            })
+            .collect();
+        assert!(insert_b1 && cancels == [String::from("s1")]);
+
+        let mut ob2 = OrderBook::<TestOrder>::default();
+        setup_order_with_owner(&mut ob2, "s1", false, 1000, 100, "alice");
+        let outputs: Vec<_> = i.into_iter().map(|instr| ob2.apply(instr)).collect();
+        assert!(outputs.contains(&Output::Inserted(String::from("b1"), 100)));
+        assert!(outputs.contains(&Output::Deleted(String::from("s1"))));
+    }
+
+    #[test]
+    fn test_stp_cancel_maker_fill_from_others() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order_with_owner(&mut ob, "s1", false, 1000, 50, "alice");
+        setup_order_with_owner(&mut ob, "s2", false, 1000, 50, "bob");
+        let order = TestOrder::new("b1", true, 1000, 100)
+            .with_owner("alice")
+            .with_stp(STP::CancelMaker);
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(order.clone())).collect();
+        let fills: Vec<_> = i
+            .iter()
+            .filter_map(|p| match p {
+                Instruction::Fill(id, _, _, q, false) => Some((id.clone(), *q)),
+                _ => None,
+            })
+            .collect();
+        let cancel_ids: Vec<_> = i
             .iter()
             .filter_map(|p| match p {
                 Instruction::Delete(id, _) => Some(id.clone()),
                 _ => None,
             })
             .collect();
-        assert!(insert_b1 && cancels == &[String::from("s1")]);
+        assert!(
+            i.iter()
+                .any(|p| matches!(p, Instruction::Insert(o, r) if o.id() == "b1" && *r == 50))
+        );
+        assert_eq!(fills, &[(String::from("s2"), 50)]);
+        assert_eq!(cancel_ids, &[String::from("s1")]);
+    }
+
+    #[test]
+    fn test_stp_cancel_both() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order_with_owner(&mut ob, "s1", false, 1000, 100, "alice");
+        let order = TestOrder::new("b1", true, 1000, 100)
+            .with_owner("alice")
+            .with_stp(STP::CancelBoth);
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(order)).collect();
+        assert_eq!(
+            i,
+            vec![
+                Instruction::NoOp(String::from("b1"), Msg::StpCancelBoth),
+                Instruction::Delete(String::from("s1"), Msg::StpCancelBoth),
+            ]
+        );
+        let mut ob2 = OrderBook::<TestOrder>::default();
+        setup_order_with_owner(&mut ob2, "s1", false, 1000, 100, "alice");
+        let outputs: Vec<_> = i.into_iter().map(|instr| ob2.apply(instr)).collect();
+        assert_eq!(
+            outputs
+                .iter()
+                .filter(|p| matches!(p, Output::Deleted(id) if id == "s1"))
+                .count(),
+            1
+        );
+        assert_eq!(
+            outputs
+                .iter()
+                .filter(|p| matches!(p, Output::NoOp(id) if id == "b1"))
+                .count(),
+            1
+        );
+        assert!(ob2.is_empty());
+    }
+
+    #[test]
+    fn test_stp_decrement_taker_larger() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order_with_owner(&mut ob, "s1", false, 1000, 30, "alice");
+        setup_order_with_owner(&mut ob, "s2", false, 1000, 50, "bob");
+        let order = TestOrder::new("b1", true, 1000, 100)
+            .with_owner("alice")
+            .with_stp(STP::DecrementAndCancel);
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("alice"), 1000, 50, true),
+                Instruction::Fill(String::from("s2"), String::from("bob"), 1000, 50, false),
+                Instruction::Delete(String::from("s1"), Msg::StpDecrementAndCancel),
+                Instruction::Insert(
+                    TestOrder::new("b1", true, 1000, 70)
+                        .with_owner("alice")
+                        .with_stp(STP::DecrementAndCancel),
+                    20
+                ),
+            ]
+        );
+        let b1 = ob.order(&String::from("b1")).unwrap();
+        assert_eq!((b1.quantity(), b1.remaining()), (70, 20));
+        assert!(ob.asks.is_empty());
+    }
+
+    #[test]
+    fn test_stp_decrement_maker_larger() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order_with_owner(&mut ob, "s1", false, 1000, 20, "bob");
+        setup_order_with_owner(&mut ob, "s2", false, 1000, 100, "alice");
+        let order = TestOrder::new("b1", true, 1000, 50)
+            .with_owner("alice")
+            .with_stp(STP::DecrementAndCancel);
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("alice"), 1000, 20, true),
+                Instruction::Fill(String::from("s1"), String::from("bob"), 1000, 20, false),
+                Instruction::Reduce(String::from("s2"), 30),
+                Instruction::Delete(String::from("b1"), Msg::StpDecrementAndCancel),
+            ]
+        );
+        assert_eq!(ob.best_ask(), Some((1000, 70)));
+        assert!(ob.bids.is_empty());
+    }
+
+    #[test]
+    fn test_stp_decrement_equal_cancels_both() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order_with_owner(&mut ob, "s1", false, 1000, 50, "alice");
+        let order = TestOrder::new("b1", true, 1000, 50)
+            .with_owner("alice")
+            .with_stp(STP::DecrementAndCancel);
+        let i = drive(&mut ob, Op::Insert(order));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Delete(String::from("s1"), Msg::StpDecrementAndCancel),
+                Instruction::Delete(String::from("b1"), Msg::StpDecrementAndCancel),
+            ]
+        );
+        assert!(ob.is_empty());
+    }
+
+    #[test]
+    fn test_stp_rejected_taker_leaves_makers() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order_with_owner(&mut ob, "s1", false, 1000, 5, "alice");
+        let fok = TestOrder::new("b1", true, 1000, 20)
+            .with_owner("alice")
+            .with_tif(TIF::FOK)
+            .with_stp(STP::DecrementAndCancel);
+        let min_qty = TestOrder::new("b2", true, 1000, 20)
+            .with_owner("alice")
+            .with_min_qty(10, false)
+            .with_stp(STP::CancelMaker);
+        // Same batch: s1 is still there for the next taker.
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(fok)).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b1"), Msg::FOKNotFilled)]
+        );
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(min_qty)).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b2"), Msg::MinQtyNotFilled)]
+        );
+        let i: Vec<_> = eval
+            .eval(&ob, Op::Insert(TestOrder::new("b3", true, 1000, 5)))
+            .collect();
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b3"), String::from("b3"), 1000, 5, true),
+                Instruction::Fill(String::from("s1"), String::from("alice"), 1000, 5, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stp_firm_scope() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 50)
+                .with_owner("alice")
+                .with_firm("acme"),
+        );
+        setup(
+            &mut ob,
+            TestOrder::new("s2", false, 1000, 50)
+                .with_owner("carol")
+                .with_firm("other"),
+        );
+        let taker = |id: &str, scope: StpScope| {
+            TestOrder::new(id, true, 1000, 50)
+                .with_owner("bob")
+                .with_firm("acme")
+                .with_stp(STP::CancelMaker)
+                .with_stp_scope(scope)
+        };
+        // Different accounts: no self-trade at account scope.
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval
+            .eval(&ob, Op::Insert(taker("b1", StpScope::Account)))
+            .collect();
+        assert_eq!(
+            i[1],
+            Instruction::Fill(String::from("s1"), String::from("alice"), 1000, 50, false)
+        );
+        // Same firm: s1 is cancelled and b2 fills against s2.
+        let i = drive(&mut ob, Op::Insert(taker("b2", StpScope::Firm)));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b2"), String::from("bob"), 1000, 50, true),
+                Instruction::Fill(String::from("s2"), String::from("carol"), 1000, 50, false),
+                Instruction::Delete(String::from("s1"), Msg::StpCancelMaker),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stp_group_scope_needs_both_keys() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("s1", false, 1000, 50)
+                .with_owner("alice")
+                .with_stp_group("desk"),
+        );
+        let taker = TestOrder::new("b1", true, 1000, 50)
+            .with_owner("bob")
+            .with_stp(STP::CancelTaker)
+            .with_stp_scope(StpScope::Group);
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, Op::Insert(taker.clone())).collect();
+        assert_eq!(i.len(), 2);
+        let i: Vec<_> = Evaluator::default()
+            .eval(&ob, Op::Insert(taker.with_stp_group("desk")))
+            .collect();
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b1"), Msg::StpCancelTaker)]
+        );
+    }
 
-        let mut ob2 = OrderBook::<TestOrder>::default();
-        setup_order_with_owner(&mut ob2, "s1", false, 1000, 100, "alice");
-        let outputs: Vec<_> = i.into_iter().map(|instr| ob2.apply(instr)).collect();
-        assert!(outputs.contains(&Output::Inserted(String::from("b1"), 100)));
-        assert!(outputs.contains(&Output::Deleted(String::from("s1"))));
+    // ─────────────────────────────────────────────────────────────────────────
+    // Quote tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    fn quote(bid: Option<(&str, u64, u64)>, ask: Option<(&str, u64, u64)>) -> Op<TestOrder> {
+        let order =
+            |(id, price, qty), is_buy| TestOrder::new(id, is_buy, price, qty).with_owner("mm");
+        Op::Quote {
+            owner: String::from("mm"),
+            bid: bid.map(|side| order(side, true)),
+            ask: ask.map(|side| order(side, false)),
+        }
     }
 
     #[test]
-    fn test_stp_cancel_maker_fill_from_others() {
+    fn test_quote_replaces_both_sides() {
         let mut ob = OrderBook::<TestOrder>::default();
-        setup_order_with_owner(&mut ob, "s1", false, 1000, 50, "alice");
-        setup_order_with_owner(&mut ob, "s2", false, 1000, 50, "bob");
-        let order = TestOrder::new("b1", true, 1000, 100)
-            .with_owner("alice")
-            .with_stp(STP::CancelMaker);
+        let i = drive(
+            &mut ob,
+            quote(Some(("b1", 990, 10)), Some(("a1", 1010, 10))),
+        );
+        assert_eq!(i.len(), 3);
+        assert_eq!(
+            i[2],
+            Instruction::Quote(
+                String::from("mm"),
+                Some(String::from("b1")),
+                Some(String::from("a1"))
+            )
+        );
+        setup_order(&mut ob, "x1", true, 990, 10);
+
+        // Quantity-only decrease keeps priority; a new ask id replaces the old quote.
+        let i = drive(&mut ob, quote(Some(("b1", 990, 5)), Some(("a2", 1011, 10))));
+        assert_eq!(i[0], Instruction::Replace(String::from("b1"), 990, 5, 5));
+        assert_eq!(
+            i[1],
+            Instruction::Delete(String::from("a1"), Msg::UserCancelled)
+        );
+        assert!(matches!(i[2], Instruction::Insert(_, 10)));
+        let level = ob.bids().next().unwrap();
+        assert_eq!(level.iter().next().unwrap().id(), "b1");
+        assert_eq!(ob.best_ask(), Some((1011, 10)));
+        let (bid, ask) = ob.quotes(&String::from("mm"));
+        assert_eq!(bid.unwrap().remaining(), 5);
+        assert_eq!(ask.unwrap().id(), "a2");
+
+        // Pulling both sides.
+        let i = drive(&mut ob, quote(None, None));
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Delete(String::from("b1"), Msg::UserCancelled),
+                Instruction::Delete(String::from("a2"), Msg::UserCancelled),
+                Instruction::Quote(String::from("mm"), None, None),
+            ]
+        );
+        assert_eq!(ob.quotes(&String::from("mm")), (None, None));
+        assert_eq!(ob.best_bid(), Some((990, 10)));
+    }
+
+    #[test]
+    fn test_quote_rejected_as_a_whole() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        drive(
+            &mut ob,
+            quote(Some(("b1", 990, 10)), Some(("a1", 1010, 10))),
+        );
+        setup_order(&mut ob, "x1", false, 1020, 10);
+
+        // Crossed, reused id and wrong owner all leave the current quotes alone.
+        let crossed = quote(Some(("b2", 1010, 10)), Some(("a2", 1005, 10)));
+        let reused = quote(Some(("b2", 990, 10)), Some(("x1", 1012, 10)));
+        let other = Op::Quote {
+            owner: String::from("mm"),
+            bid: Some(TestOrder::new("b2", true, 990, 10).with_owner("alice")),
+            ask: None,
+        };
+        let same_id = quote(Some(("b2", 990, 10)), Some(("b2", 1012, 10)));
+        let fok = Op::Quote {
+            owner: String::from("mm"),
+            bid: Some(TestOrder::new("b2", true, 990, 10).with_owner("mm")),
+            ask: Some(
+                TestOrder::new("a2", false, 1012, 10)
+                    .with_owner("mm")
+                    .with_tif(TIF::FOK),
+            ),
+        };
+        for op in [crossed, reused, other, same_id, fok] {
+            let i = drive(&mut ob, op);
+            assert_eq!(
+                i,
+                vec![Instruction::NoOp(String::from("b2"), Msg::QuoteRejected)]
+            );
+        }
+        assert_eq!(ob.best_bid(), Some((990, 10)));
+        assert_eq!(ob.best_ask(), Some((1010, 10)));
+    }
+
+    #[test]
+    fn test_quote_pulls_old_quotes_first() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        drive(&mut ob, quote(Some(("b1", 99, 10)), Some(("a1", 100, 10))));
+
+        // The new bid crosses the old ask, which goes first.
+        let i = drive(&mut ob, quote(Some(("b2", 101, 10)), Some(("a2", 102, 10))));
+        assert_eq!(
+            i[..2],
+            [
+                Instruction::Delete(String::from("b1"), Msg::UserCancelled),
+                Instruction::Delete(String::from("a1"), Msg::UserCancelled),
+            ]
+        );
+        assert!(matches!(
+            i[2..4],
+            [Instruction::Insert(_, 10), Instruction::Insert(_, 10)]
+        ));
+        assert_eq!(ob.best_bid(), Some((101, 10)));
+        assert_eq!(ob.best_ask(), Some((102, 10)));
+
+        // Same ids moving up together do not trade either.
+        let i = drive(&mut ob, quote(Some(("b2", 103, 10)), Some(("a2", 104, 10))));
+        assert_eq!(
+            i[..2],
+            [
+                Instruction::Replace(String::from("b2"), 103, 10, 10),
+                Instruction::Replace(String::from("a2"), 104, 10, 10),
+            ]
+        );
+        assert_eq!(ob.best_bid(), Some((103, 10)));
+        assert_eq!(ob.best_ask(), Some((104, 10)));
+        let (bid, ask) = ob.quotes(&String::from("mm"));
+        assert_eq!(
+            (bid.unwrap().id().as_str(), ask.unwrap().id().as_str()),
+            ("b2", "a2")
+        );
+    }
+
+    #[test]
+    fn test_quote_may_trade() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 4);
+        let i = drive(
+            &mut ob,
+            quote(Some(("b1", 1000, 10)), Some(("a1", 1010, 10))),
+        );
+        assert_eq!(
+            i[..3],
+            [
+                Instruction::Fill(String::from("b1"), String::from("mm"), 1000, 4, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 4, false),
+                Instruction::Insert(TestOrder::new("b1", true, 1000, 10).with_owner("mm"), 6),
+            ]
+        );
+        assert_eq!(ob.best_bid(), Some((1000, 6)));
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Replace tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    fn replace(id: &str, new_price: u64, new_quantity: u64) -> Op<TestOrder> {
+        Op::Replace {
+            id: String::from(id),
+            new_price,
+            new_quantity,
+        }
+    }
+
+    #[test]
+    fn test_replace_decrease_keeps_priority() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 100);
+        setup_order(&mut ob, "s2", false, 1000, 50);
         let mut eval = Evaluator::default();
-        let i: Vec<_> = eval.eval(&ob, Op::Insert(order.clone())).collect();
-        let fills: Vec<_> = i
-            .iter()
-            .filter_map(|p| match p {
-                Instruction::Fill(id, _, _, q, false) => Some((id.clone(), *q)),
-                _ => None,
-            })
-            .collect();
-        let cancel_ids: Vec<_> = i
-            .iter()
-            .filter_map(|p| match p {
-                Instruction::Delete(id, _) => Some(id.clone()),
-                _ => None,
-            })
-            .collect();
-        assert!(
-            i.iter()
-                .any(|p| matches!(p, Instruction::Insert(o, r) if o.id() == "b1" && *r == 50))
+        let i: Vec<_> = eval.eval(&ob, replace("s1", 1000, 60)).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::Replace(String::from("s1"), 1000, 60, 60)]
         );
-        assert_eq!(fills, &[(String::from("s2"), 50)]);
-        assert_eq!(cancel_ids, &[String::from("s1")]);
+        for instr in i {
+            assert_eq!(ob.apply(instr), Output::Replaced(String::from("s1"), 60));
+        }
+        assert_eq!(ob.best_ask(), Some((1000, 110)));
+        let ids: Vec<_> = ob.asks().next().unwrap().iter().map(|o| o.id()).collect();
+        assert_eq!(ids, vec!["s1", "s2"]);
+        assert_eq!(ob.order(&String::from("s1")).unwrap().quantity(), 60);
     }
 
     #[test]
-    fn test_stp_cancel_both() {
+    fn test_replace_keeps_filled_quantity() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 100);
+        ob.apply(Instruction::Fill(
+            String::from("s1"),
+            String::from("s1"),
+            1000,
+            30,
+            false,
+        ));
+        let mut eval = Evaluator::default();
+        // 30 already filled: new quantity 50 leaves 20 open.
+        let i: Vec<_> = eval.eval(&ob, replace("s1", 1000, 50)).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::Replace(String::from("s1"), 1000, 50, 20)]
+        );
+        for instr in i {
+            ob.apply(instr);
+        }
+        let order = ob.order(&String::from("s1")).unwrap();
+        assert_eq!((order.quantity(), order.remaining()), (50, 20));
+
+        // New quantity at or below filled cancels the order.
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, replace("s1", 1000, 30)).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::Delete(String::from("s1"), Msg::UserCancelled)]
+        );
+    }
+
+    #[test]
+    fn test_replace_increase_loses_priority() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 100);
+        setup_order(&mut ob, "s2", false, 1000, 50);
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, replace("s1", 1000, 150)).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::Replace(String::from("s1"), 1000, 150, 150)]
+        );
+        for instr in i {
+            ob.apply(instr);
+        }
+        assert_eq!(ob.best_ask(), Some((1000, 200)));
+        let ids: Vec<_> = ob.asks().next().unwrap().iter().map(|o| o.id()).collect();
+        assert_eq!(ids, vec!["s2", "s1"]);
+    }
+
+    #[test]
+    fn test_replace_price_change_matches() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "b1", true, 990, 100);
+        setup_order(&mut ob, "s1", false, 1000, 40);
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, replace("b1", 1000, 100)).collect();
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 40, true),
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 40, false),
+                Instruction::Replace(String::from("b1"), 1000, 100, 60),
+            ]
+        );
+        // Moved order is invisible to later ops in the batch until applied.
+        let i2: Vec<_> = eval.eval(&ob, Op::Delete(String::from("b1"))).collect();
+        assert_eq!(
+            i2,
+            vec![Instruction::NoOp(String::from("b1"), Msg::OrderNotFound)]
+        );
+        for instr in i {
+            ob.apply(instr);
+        }
+        assert!(ob.asks.is_empty());
+        assert_eq!(ob.best_bid(), Some((1000, 60)));
+        let order = ob.order(&String::from("b1")).unwrap();
+        assert_eq!((order.quantity(), order.remaining()), (100, 60));
+    }
+
+    #[test]
+    fn test_replace_full_fill_removes() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "b1", true, 990, 100);
+        setup_order(&mut ob, "s1", false, 1000, 100);
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, replace("b1", 1000, 100)).collect();
+        let outputs: Vec<_> = i.into_iter().map(|instr| ob.apply(instr)).collect();
+        assert_eq!(outputs.last(), Some(&Output::Filled(String::from("b1"))));
+        assert!(ob.is_empty());
+    }
+
+    #[test]
+    fn test_replace_not_found() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, replace("x", 1000, 10)).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("x"), Msg::OrderNotFound)]
+        );
+
+        setup_order(&mut ob, "b1", true, 1000, 100);
+        let _: Vec<_> = eval.eval(&ob, Op::Delete(String::from("b1"))).collect();
+        let i: Vec<_> = eval.eval(&ob, replace("b1", 1000, 10)).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b1"), Msg::OrderNotFound)]
+        );
+    }
+
+    #[test]
+    fn test_replace_post_only_rejected() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("b1", true, 990, 100).with_post_only(true),
+        );
+        setup_order(&mut ob, "s1", false, 1000, 100);
+        let mut eval = Evaluator::default();
+        let i: Vec<_> = eval.eval(&ob, replace("b1", 1000, 100)).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("b1"), Msg::PostOnlyFilled)]
+        );
+    }
+
+    #[test]
+    fn test_replace_stp_cancel_both() {
         let mut ob = OrderBook::<TestOrder>::default();
+        setup(
+            &mut ob,
+            TestOrder::new("b1", true, 990, 100)
+                .with_owner("alice")
+                .with_stp(STP::CancelBoth),
+        );
         setup_order_with_owner(&mut ob, "s1", false, 1000, 100, "alice");
-        let order = TestOrder::new("b1", true, 1000, 100)
-            .with_owner("alice")
-            .with_stp(STP::CancelBoth);
         let mut eval = Evaluator::default();
-        let i: Vec<_> = eval.eval(&ob, Op::Insert(order)).collect();
+        let i: Vec<_> = eval.eval(&ob, replace("b1", 1000, 100)).collect();
         assert_eq!(
             i,
             vec![
-                Instruction::NoOp(String::from("b1"), Msg::StpCancelBoth),
+                Instruction::Delete(String::from("b1"), Msg::StpCancelBoth),
                 Instruction::Delete(String::from("s1"), Msg::StpCancelBoth),
             ]
         );
-        let mut ob2 = OrderBook::<TestOrder>::default();
-        setup_order_with_owner(&mut ob2, "s1", false, 1000, 100, "alice");
-        let outputs: Vec<_> = i.into_iter().map(|instr| ob2.apply(instr)).collect();
+        for instr in i {
+            ob.apply(instr);
+        }
+        assert!(ob.is_empty());
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Reduce tests
+    // ─────────────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_reduce_keeps_priority() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "b1", true, 1000, 100);
+        setup_order(&mut ob, "b2", true, 1000, 50);
+        let mut eval = Evaluator::default();
+        let op = Op::Reduce {
+            id: String::from("b1"),
+            quantity: 70,
+        };
+        let i: Vec<_> = eval.eval(&ob, op).collect();
+        assert_eq!(i, vec![Instruction::Reduce(String::from("b1"), 70)]);
+        for instr in i {
+            assert_eq!(ob.apply(instr), Output::Reduced(String::from("b1"), 30));
+        }
+        assert_eq!(ob.best_bid(), Some((1000, 80)));
+
+        // b1 is still first in queue.
+        eval.reset();
+        let i: Vec<_> = eval
+            .eval(&ob, Op::Insert(TestOrder::new("s1", false, 1000, 30)))
+            .collect();
         assert_eq!(
-            outputs
-                .iter()
-                .filter(|p| matches!(p, Output::Deleted(id) if id == "s1"))
-                .count(),
-            1
+            i,
+            vec![
+                Instruction::Fill(String::from("s1"), String::from("s1"), 1000, 30, true),
+                Instruction::Fill(String::from("b1"), String::from("b1"), 1000, 30, false),
+            ]
         );
+    }
+
+    #[test]
+    fn test_reduce_uses_temp_state() {
+        let mut ob = OrderBook::<TestOrder>::default();
+        setup_order(&mut ob, "s1", false, 1000, 100);
+        let mut eval = Evaluator::default();
+        let _: Vec<_> = eval
+            .eval(&ob, Op::Insert(TestOrder::new("b1", true, 1000, 60)))
+            .collect();
+        // Only 40 virtually left: reducing by 40 cancels.
+        let op = Op::Reduce {
+            id: String::from("s1"),
+            quantity: 40,
+        };
+        let i: Vec<_> = eval.eval(&ob, op).collect();
         assert_eq!(
-            outputs
-                .iter()
-                .filter(|p| matches!(p, Output::NoOp(id) if id == "b1"))
-                .count(),
-            1
+            i,
+            vec![Instruction::Delete(String::from("s1"), Msg::UserCancelled)]
+        );
+        let op = Op::Reduce {
+            id: String::from("s1"),
+            quantity: 10,
+        };
+        let i: Vec<_> = eval.eval(&ob, op).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("s1"), Msg::OrderNotFound)]
+        );
+        let op = Op::Reduce {
+            id: String::from("x"),
+            quantity: 10,
+        };
+        let i: Vec<_> = eval.eval(&ob, op).collect();
+        assert_eq!(
+            i,
+            vec![Instruction::NoOp(String::from("x"), Msg::OrderNotFound)]
         );
-        assert!(ob2.is_empty());
     }
 
     // ─────────────────────────────────────────────────────────────────────────
@@ -974,7 +4195,9 @@ mod tests {
         let i1: Vec<_> = eval
             .eval(&ob, Op::Insert(TestOrder::new("b1", true, 1000, 100)))
             .collect();
-        assert!(i1.iter().any(|p| matches!(p, Instruction::Fill(id, _, _, q, false) if id == "s1" && *q == 100)));
+        assert!(i1.iter().any(
+            |p| matches!(p, Instruction::Fill(id, _, _, q, false) if id == "s1" && *q == 100)
+        ));
 
         let i2: Vec<_> = eval.eval(&ob, Op::Delete(String::from("s1"))).collect();
         assert_eq!(
@@ -1011,7 +4234,11 @@ mod tests {
         let i1: Vec<_> = eval
             .eval(&ob, Op::Insert(TestOrder::new("b1", true, 1000, 40)))
             .collect();
-        assert!(i1.iter().any(|p| matches!(p, Instruction::Fill(id, _, _, q, false) if id == "s1" && *q == 40)));
+        assert!(
+            i1.iter().any(
+                |p| matches!(p, Instruction::Fill(id, _, _, q, false) if id == "s1" && *q == 40)
+            )
+        );
 
         let i2: Vec<_> = eval.eval(&ob, Op::Delete(String::from("s1"))).collect();
         assert_eq!(
@@ -14,9 +14,147 @@ pub enum TIF {
     FOK,
     /// Fill as much as possible immediately, cancel the rest (no resting quantity).
     IOC,
+    /// Good till date; rests until `expire_at()`, typically the end of the given day.
+    GTD,
+    /// Good till time; rests until `expire_at()`.
+    GTT,
+    /// Rests until the end of the trading session.
+    Day,
 }
 
-/// Self-trade protection mode when taker and maker share the same owner.
+impl TIF {
+    /// Returns true if orders with this TIF expire at `OrderInterface::expire_at()`.
+    #[inline(always)]
+    pub fn expires(&self) -> bool {
+        matches!(self, TIF::GTD | TIF::GTT)
+    }
+}
+
+/// Order type: how the order's price limit is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrderType {
+    /// Trades up to `price()`; remainder handled by TIF.
+    #[default]
+    Limit,
+    /// Sweeps the opposite side with no price limit; remainder is cancelled.
+    Market,
+    /// Sweeps like a market order; remainder rests as a limit at the last fill price.
+    MarketToLimit,
+}
+
+/// A price offset, either fixed or proportional to a reference price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Offset<N> {
+    /// Fixed distance from the reference.
+    Fixed(N),
+    /// `reference * num / den`, e.g. `Ratio(1, 100)` for 1%.
+    Ratio(N, N),
+}
+
+impl<N> Offset<N>
+where
+    N: Copy + std::ops::Mul<Output = N> + std::ops::Div<Output = N>,
+{
+    /// Returns the offset distance for `reference`.
+    #[inline]
+    pub fn distance(&self, reference: N) -> N {
+        match *self {
+            Offset::Fixed(distance) => distance,
+            Offset::Ratio(num, den) => reference * num / den,
+        }
+    }
+}
+
+/// Reference price a trailing stop follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailRef {
+    /// Best price on the side the stop would trade against
+    /// (best ask for buy stops, best bid for sell stops).
+    #[default]
+    Best,
+    /// Last traded price.
+    LastTrade,
+}
+
+/// Trailing stop: the trigger follows `reference` at `offset`, only ever moving toward
+/// the market (up for sell stops, down for buy stops).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trail<N> {
+    pub offset: Offset<N>,
+    pub reference: TrailRef,
+}
+
+/// Reference price a pegged order follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PegRef {
+    /// Best price on the order's own side (best bid for buys, best ask for sells).
+    #[default]
+    Primary,
+    /// Best price on the opposite side (best ask for buys, best bid for sells).
+    Market,
+}
+
+/// Pegged order: the price follows `reference`, shifted by `offset` and limited by `cap`.
+/// Other pegged orders never count toward the reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peg<N> {
+    pub reference: PegRef,
+    /// Distance from the reference: toward the opposite side if `aggressive`, away
+    /// from it otherwise.
+    pub offset: N,
+    pub aggressive: bool,
+    /// Price the peg never goes past (highest for buys, lowest for sells).
+    pub cap: Option<N>,
+}
+
+impl<N> Peg<N>
+where
+    N: Ord + Copy + Default + Add<Output = N> + Sub<Output = N>,
+{
+    /// Returns the pegged price given the best unpegged bid and ask, or `None` if the
+    /// reference is missing.
+    #[inline]
+    pub fn price(&self, is_buy: bool, best_bid: Option<N>, best_ask: Option<N>) -> Option<N> {
+        let reference = match (self.reference, is_buy) {
+            (PegRef::Primary, true) | (PegRef::Market, false) => best_bid,
+            (PegRef::Primary, false) | (PegRef::Market, true) => best_ask,
+        }?;
+        let price = if is_buy == self.aggressive {
+            reference + self.offset
+        } else if reference > self.offset {
+            reference - self.offset
+        } else {
+            N::default()
+        };
+        Some(match self.cap {
+            Some(cap) if is_buy => price.min(cap),
+            Some(cap) => price.max(cap),
+            None => price,
+        })
+    }
+}
+
+/// Minimum quantity execution condition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MinQty<N> {
+    /// Least quantity the order must fill on entry (or all of it, if less remains);
+    /// otherwise it is cancelled without trading.
+    pub quantity: N,
+    /// If true, the order also only trades against takers filling at least `quantity`
+    /// of it (or all of it, if less remains) while it rests.
+    pub resting: bool,
+}
+
+impl<N: Ord + Copy> MinQty<N> {
+    /// Returns true if a fill of `fill` out of `remaining` meets the minimum.
+    #[inline(always)]
+    pub fn accepts(&self, fill: N, remaining: N) -> bool {
+        fill >= self.quantity.min(remaining)
+    }
+}
+
+/// Self-trade protection mode when taker and maker share an STP key at the taker's
+/// `stp_scope()` (by default, the same owner).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum STP {
     /// No self-trade protection.
@@ -28,6 +166,42 @@ pub enum STP {
     CancelMaker,
     /// Cancel both taker and maker(s) involved in the self-trade.
     CancelBoth,
+    /// Cancel the smaller of taker and maker and reduce the larger by the same quantity,
+    /// without a trade. Equal quantities cancel both.
+    DecrementAndCancel,
+}
+
+/// Scope at which self-trade protection applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StpScope {
+    /// Same account (`owner()`).
+    #[default]
+    Account,
+    /// Same trading firm.
+    Firm,
+    /// Same explicit STP group.
+    Group,
+}
+
+/// Self-trade keys of an order, one per scope. Two orders self-trade at a scope when both
+/// have a key there and the keys are equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StpKeys<'a, O> {
+    pub account: &'a O,
+    pub firm: Option<&'a O>,
+    pub group: Option<&'a O>,
+}
+
+impl<'a, O> StpKeys<'a, O> {
+    /// Returns the key at `scope`, if any.
+    #[inline(always)]
+    pub fn get(&self, scope: StpScope) -> Option<&'a O> {
+        match scope {
+            StpScope::Account => Some(self.account),
+            StpScope::Firm => self.firm,
+            StpScope::Group => self.group,
+        }
+    }
 }
 
 /// Trait defining the interface for orders in the orderbook.
@@ -66,22 +240,114 @@ pub trait OrderInterface {
     /// Fill the order, updating remaining quantity.
     fn fill(&mut self, quantity: Self::N);
 
+    /// Amend price and original quantity, resetting remaining to `quantity`.
+    /// The book re-applies any filled quantity through `fill` afterwards.
+    fn amend(&mut self, price: Self::N, quantity: Self::N);
+
     /// Owner id for self-trade protection.
     fn owner(&self) -> &Self::O;
 
-    /// Time in force: FOK, IOC, or GTC. Default is GTC.
+    /// Order type: Limit, Market, or MarketToLimit. Default is Limit.
+    /// `price()` is ignored while a market order sweeps.
+    fn order_type(&self) -> OrderType {
+        OrderType::Limit
+    }
+
+    /// Stop trigger price. If set, the order is held off the book until the last trade
+    /// price reaches it (buy: at or above, sell: at or below), then enters as
+    /// `order_type()` (Market: stop-market, Limit: stop-limit).
+    fn stop_price(&self) -> Option<Self::N> {
+        None
+    }
+
+    /// Trailing stop parameters. If set, the order is held off the book like a stop,
+    /// with a trigger that follows the market instead of `stop_price()`.
+    fn trail(&self) -> Option<Trail<Self::N>> {
+        None
+    }
+
+    /// Iceberg display quantity. If set, only a slice of this size is shown in the
+    /// level totals; when it fills, the next slice comes from the reserve and the order
+    /// moves to the back of its level.
+    fn display_quantity(&self) -> Option<Self::N> {
+        None
+    }
+
+    /// Peg parameters. If set, the order is priced from the book on entry and repriced
+    /// whenever the best unpegged bid or ask moves; `price()` is overwritten through
+    /// `amend`.
+    fn peg(&self) -> Option<Peg<Self::N>> {
+        None
+    }
+
+    /// Minimum quantity condition. If set, the order is cancelled on entry unless it can
+    /// fill at least the minimum immediately; see `MinQty`.
+    fn min_qty(&self) -> Option<MinQty<Self::N>> {
+        None
+    }
+
+    /// Time in force: GTC, FOK, IOC, GTD, GTT, or Day. Default is GTC.
     fn tif(&self) -> TIF {
         TIF::GTC
     }
 
+    /// Expiry timestamp for GTD/GTT orders, in the book clock's units. The order expires
+    /// once the clock reaches it. Ignored for other TIFs; GTD/GTT without it act as GTC.
+    fn expire_at(&self) -> Option<u64> {
+        None
+    }
+
     fn stp(&self) -> STP {
         STP::None
     }
 
+    /// Scope at which this order's `stp()` applies when it takes. Default is Account.
+    fn stp_scope(&self) -> StpScope {
+        StpScope::Account
+    }
+
+    /// Self-trade keys. Default is `owner()` as the account key and no firm or group.
+    fn stp_keys(&self) -> StpKeys<'_, Self::O> {
+        StpKeys {
+            account: self.owner(),
+            firm: None,
+            group: None,
+        }
+    }
+
+    /// If true, the order only ever trades in full: as a taker it rests (or is handled
+    /// by TIF) untouched unless it can fill completely on entry, and as a maker it is
+    /// skipped by takers too small to fill all of it. Not counted in level totals.
+    fn all_or_none(&self) -> bool {
+        false
+    }
+
+    /// Discretionary price, beyond `price()` toward the opposite side (higher for buys,
+    /// lower for sells). If set, the resting order shows `price()` but also trades with
+    /// takers whose limit stops short of it, as long as the limit is within discretion,
+    /// at the taker's limit price.
+    fn discretion_price(&self) -> Option<Self::N> {
+        None
+    }
+
+    /// If true, the order is not displayed: it trades at its price but is left out of
+    /// level totals and best prices, and queues behind all displayed orders at its level.
+    /// `display_quantity()` is ignored for hidden and all-or-none orders.
+    fn hidden(&self) -> bool {
+        false
+    }
+
     /// If true, order must not take; it is rejected if it would cross the spread.
     fn post_only(&self) -> bool {
         false
     }
+
+    /// If true, a post-only order that would cross on entry is repriced one tick behind
+    /// the opposite best price and rests there instead of being rejected. Needs a book
+    /// tick size; replaces are still rejected.
+    fn post_only_slide(&self) -> bool {
+        false
+    }
 }
 
 #[cfg(test)]
@@ -95,7 +361,21 @@ pub struct TestOrder {
     tif: TIF,
     stp: STP,
     post_only: bool,
+    post_only_slide: bool,
     owner: String,
+    order_type: OrderType,
+    stop_price: Option<u64>,
+    trail: Option<Trail<u64>>,
+    display_quantity: Option<u64>,
+    peg: Option<Peg<u64>>,
+    expire_at: Option<u64>,
+    min_qty: Option<MinQty<u64>>,
+    all_or_none: bool,
+    hidden: bool,
+    discretion_price: Option<u64>,
+    stp_scope: StpScope,
+    firm: Option<String>,
+    stp_group: Option<String>,
 }
 
 #[cfg(test)]
@@ -110,10 +390,74 @@ impl TestOrder {
             tif: TIF::GTC,
             stp: STP::None,
             post_only: false,
+            post_only_slide: false,
             owner: id.to_string(),
+            order_type: OrderType::Limit,
+            stop_price: None,
+            trail: None,
+            display_quantity: None,
+            peg: None,
+            expire_at: None,
+            min_qty: None,
+            all_or_none: false,
+            hidden: false,
+            discretion_price: None,
+            stp_scope: StpScope::Account,
+            firm: None,
+            stp_group: None,
         }
     }
 
+    pub fn with_discretion_price(mut self, discretion_price: u64) -> Self {
+        self.discretion_price = Some(discretion_price);
+        self
+    }
+
+    pub fn with_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    pub fn with_all_or_none(mut self, all_or_none: bool) -> Self {
+        self.all_or_none = all_or_none;
+        self
+    }
+
+    pub fn with_min_qty(mut self, quantity: u64, resting: bool) -> Self {
+        self.min_qty = Some(MinQty { quantity, resting });
+        self
+    }
+
+    pub fn with_expire_at(mut self, expire_at: u64) -> Self {
+        self.expire_at = Some(expire_at);
+        self
+    }
+
+    pub fn with_peg(mut self, peg: Peg<u64>) -> Self {
+        self.peg = Some(peg);
+        self
+    }
+
+    pub fn with_display_quantity(mut self, display_quantity: u64) -> Self {
+        self.display_quantity = Some(display_quantity);
+        self
+    }
+
+    pub fn with_trail(mut self, trail: Trail<u64>) -> Self {
+        self.trail = Some(trail);
+        self
+    }
+
+    pub fn with_stop_price(mut self, stop_price: u64) -> Self {
+        self.stop_price = Some(stop_price);
+        self
+    }
+
+    pub fn with_order_type(mut self, order_type: OrderType) -> Self {
+        self.order_type = order_type;
+        self
+    }
+
     pub fn with_tif(mut self, tif: TIF) -> Self {
         self.tif = tif;
         self
@@ -124,11 +468,32 @@ impl TestOrder {
         self
     }
 
+    pub fn with_post_only_slide(mut self) -> Self {
+        self.post_only = true;
+        self.post_only_slide = true;
+        self
+    }
+
     pub fn with_stp(mut self, stp: STP) -> Self {
         self.stp = stp;
         self
     }
 
+    pub fn with_stp_scope(mut self, stp_scope: StpScope) -> Self {
+        self.stp_scope = stp_scope;
+        self
+    }
+
+    pub fn with_firm(mut self, firm: &str) -> Self {
+        self.firm = Some(firm.to_string());
+        self
+    }
+
+    pub fn with_stp_group(mut self, stp_group: &str) -> Self {
+        self.stp_group = Some(stp_group.to_string());
+        self
+    }
+
     pub fn with_owner(mut self, owner: &str) -> Self {
         self.owner = owner.to_string();
         self
@@ -165,19 +530,81 @@ impl OrderInterface for TestOrder {
         self.remaining -= quantity;
     }
 
+    fn amend(&mut self, price: u64, quantity: u64) {
+        self.price = price;
+        self.quantity = quantity;
+        self.remaining = quantity;
+    }
+
     fn owner(&self) -> &String {
         &self.owner
     }
 
+    fn order_type(&self) -> OrderType {
+        self.order_type
+    }
+
+    fn stop_price(&self) -> Option<u64> {
+        self.stop_price
+    }
+
+    fn trail(&self) -> Option<Trail<u64>> {
+        self.trail
+    }
+
+    fn display_quantity(&self) -> Option<u64> {
+        self.display_quantity
+    }
+
+    fn peg(&self) -> Option<Peg<u64>> {
+        self.peg
+    }
+
+    fn min_qty(&self) -> Option<MinQty<u64>> {
+        self.min_qty
+    }
+
     fn tif(&self) -> TIF {
         self.tif
     }
 
+    fn expire_at(&self) -> Option<u64> {
+        self.expire_at
+    }
+
+    fn all_or_none(&self) -> bool {
+        self.all_or_none
+    }
+
+    fn discretion_price(&self) -> Option<u64> {
+        self.discretion_price
+    }
+
+    fn hidden(&self) -> bool {
+        self.hidden
+    }
+
     fn post_only(&self) -> bool {
         self.post_only
     }
 
+    fn post_only_slide(&self) -> bool {
+        self.post_only_slide
+    }
+
     fn stp(&self) -> STP {
         self.stp
     }
+
+    fn stp_scope(&self) -> StpScope {
+        self.stp_scope
+    }
+
+    fn stp_keys(&self) -> StpKeys<'_, String> {
+        StpKeys {
+            account: &self.owner,
+            firm: self.firm.as_ref(),
+            group: self.stp_group.as_ref(),
+        }
+    }
 }
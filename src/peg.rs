@@ -0,0 +1,132 @@
+use crate::order::OrderInterface;
+
+/// (Best Unpegged Bid, Best Unpegged Ask)
+type Refs<N> = (Option<N>, Option<N>);
+
+/// Pegged orders resting on the book, in arrival order.
+///
+/// Tracks the best unpegged bid and ask the pegs were last priced against; when either
+/// moves, the pegs are marked dirty so the book can queue repricing ops.
+pub struct Pegs<O: OrderInterface> {
+    ids: Vec<O::I>,
+    refs: Refs<O::N>,
+    dirty: bool,
+}
+
+impl<O: OrderInterface> Default for Pegs<O> {
+    fn default() -> Self {
+        Self {
+            ids: Vec::new(),
+            refs: (None, None),
+            dirty: false,
+        }
+    }
+}
+
+impl<O: OrderInterface> Pegs<O> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &O::I> {
+        self.ids.iter()
+    }
+
+    #[inline]
+    pub fn insert(&mut self, order_id: O::I) {
+        self.ids.push(order_id);
+    }
+
+    #[inline]
+    pub fn remove(&mut self, order_id: &O::I) {
+        self.ids.retain(|id| id != order_id);
+    }
+
+    /// Records the current references, marking the pegs dirty if either moved.
+    #[inline]
+    pub fn update(&mut self, best_bid: Option<O::N>, best_ask: Option<O::N>) {
+        if self.refs != (best_bid, best_ask) {
+            self.refs = (best_bid, best_ask);
+            self.dirty = true;
+        }
+    }
+
+    /// Returns the references and clears the dirty flag if the pegs need repricing.
+    #[inline]
+    pub fn take_dirty(&mut self) -> Option<Refs<O::N>> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        Some(self.refs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{Peg, PegRef, TestOrder};
+
+    #[test]
+    fn test_insert_remove() {
+        let mut pegs = Pegs::<TestOrder>::default();
+        assert!(pegs.is_empty());
+        pegs.insert(String::from("1"));
+        pegs.insert(String::from("2"));
+        assert_eq!(pegs.len(), 2);
+        pegs.remove(&String::from("1"));
+        let ids: Vec<_> = pegs.iter().collect();
+        assert_eq!(ids, vec!["2"]);
+    }
+
+    #[test]
+    fn test_dirty_on_reference_move() {
+        let mut pegs = Pegs::<TestOrder>::default();
+        assert_eq!(pegs.take_dirty(), None);
+        pegs.update(Some(100), Some(110));
+        assert_eq!(pegs.take_dirty(), Some((Some(100), Some(110))));
+        pegs.update(Some(100), Some(110));
+        assert_eq!(pegs.take_dirty(), None);
+        pegs.update(None, Some(110));
+        assert_eq!(pegs.take_dirty(), Some((None, Some(110))));
+    }
+
+    #[test]
+    fn test_peg_price() {
+        let peg = Peg {
+            reference: PegRef::Primary,
+            offset: 2,
+            aggressive: false,
+            cap: None,
+        };
+        assert_eq!(peg.price(true, Some(100), Some(110)), Some(98));
+        assert_eq!(peg.price(false, Some(100), Some(110)), Some(112));
+        assert_eq!(peg.price(true, None, Some(110)), None);
+        assert_eq!(peg.price(false, Some(100), None), None);
+
+        let peg = Peg {
+            reference: PegRef::Market,
+            offset: 2,
+            aggressive: true,
+            cap: Some(111),
+        };
+        assert_eq!(peg.price(true, Some(100), Some(110)), Some(111));
+        assert_eq!(peg.price(false, Some(100), Some(110)), Some(111));
+        assert_eq!(peg.price(false, Some(1), Some(110)), Some(111));
+
+        let peg = Peg {
+            reference: PegRef::Market,
+            offset: 200,
+            aggressive: false,
+            cap: None,
+        };
+        assert_eq!(peg.price(true, None, Some(110)), Some(0));
+    }
+}
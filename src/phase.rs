@@ -0,0 +1,36 @@
+/// Trading phase of a book. Changed with `Op::Phase`, which yields an
+/// `Instruction::Phase` so every change shows up in the instruction stream.
+///
+/// | Phase      | Inserts / replaces      | Cancels / reduces |
+/// |------------|-------------------------|-------------------|
+/// | PreOpen    | rest without matching   | yes               |
+/// | Auction    | rest without matching   | yes               |
+/// | Continuous | match                   | yes               |
+/// | Halted     | `Msg::Halted`           | yes               |
+/// | Closed     | `Msg::Closed`           | yes               |
+///
+/// PreOpen and Auction are call phases: IOC/FOK orders are refused with
+/// `Msg::NotInAuction`, market orders are held for the uncross, and leaving a call phase
+/// for any other phase uncrosses the book first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Phase {
+    /// Order entry before the opening auction.
+    PreOpen,
+    /// Call auction, e.g. opening, closing or a reopening after a halt.
+    Auction,
+    /// Continuous matching, or frequent batch auctions if the book has a batch interval.
+    #[default]
+    Continuous,
+    /// Trading halted, e.g. after a price band breach.
+    Halted,
+    /// Book closed for the session.
+    Closed,
+}
+
+impl Phase {
+    /// Returns true in a call phase, where orders accumulate without matching.
+    #[inline(always)]
+    pub fn is_call(self) -> bool {
+        matches!(self, Phase::PreOpen | Phase::Auction)
+    }
+}
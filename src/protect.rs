@@ -0,0 +1,99 @@
+/// Price an order's collar is measured from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollarRef {
+    /// Best displayed opposite price when the order arrives.
+    #[default]
+    Best,
+    /// Last trade price, or the best opposite price before the first trade.
+    LastTrade,
+}
+
+/// What happens to the remainder of an order stopped at its collar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollarAction {
+    /// Cancel the remainder with `Msg::PriceCollar`.
+    #[default]
+    Cancel,
+    /// Rest the remainder at the collar price (IOC/FOK still cancel).
+    Rest,
+}
+
+/// Price protection: an incoming order, triggered stop or replace trades at most `width`
+/// away from the reference price, whatever its limit. Post-only orders are not collared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Collar<N> {
+    pub reference: CollarRef,
+    pub width: N,
+    pub action: CollarAction,
+}
+
+impl<N: Copy + Ord + Default + std::ops::Add<Output = N> + std::ops::Sub<Output = N>> Collar<N> {
+    /// Returns the worst price a buy (or sell) may trade at, given the best opposite and
+    /// last trade prices; `None` without a reference.
+    #[inline]
+    pub fn price(&self, is_buy: bool, best: Option<N>, last_trade: Option<N>) -> Option<N> {
+        let reference = match self.reference {
+            CollarRef::Best => best,
+            CollarRef::LastTrade => last_trade.or(best),
+        }?;
+        Some(worst(is_buy, reference, self.width))
+    }
+}
+
+/// Price a band is measured from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandRef<N> {
+    /// Fixed reference, e.g. the previous close.
+    Static(N),
+    /// Last trade price; no band before the first trade.
+    LastTrade,
+}
+
+/// What happens when an order would trade outside the band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BandAction {
+    /// Cancel the order's remainder with `Msg::PriceBand`.
+    #[default]
+    Reject,
+    /// Cancel the remainder and move the book to `Phase::Halted`.
+    Halt,
+}
+
+/// Price band (circuit breaker): no trade more than `width` away from the reference
+/// price. Fills inside the band stand; the order stops at the first level outside it.
+/// Applies to inserts, triggered stops and replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Band<N> {
+    pub reference: BandRef<N>,
+    pub width: N,
+    pub action: BandAction,
+}
+
+impl<N: Copy + Ord + Default + std::ops::Add<Output = N> + std::ops::Sub<Output = N>> Band<N> {
+    /// Returns the worst price a buy (or sell) may trade at given the last trade price;
+    /// `None` without a reference.
+    #[inline]
+    pub fn price(&self, is_buy: bool, last_trade: Option<N>) -> Option<N> {
+        let reference = match self.reference {
+            BandRef::Static(reference) => reference,
+            BandRef::LastTrade => last_trade?,
+        };
+        Some(worst(is_buy, reference, self.width))
+    }
+}
+
+/// Returns `reference` moved `width` against a buy (or sell), floored at zero.
+#[inline(always)]
+fn worst<N: Copy + Ord + Default + std::ops::Add<Output = N> + std::ops::Sub<Output = N>>(
+    is_buy: bool,
+    reference: N,
+    width: N,
+) -> N {
+    if is_buy {
+        reference + width
+    } else if reference > width {
+        reference - width
+    } else {
+        N::default()
+    }
+}
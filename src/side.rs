@@ -10,6 +10,8 @@ use std::collections::BTreeMap;
 pub struct Side<O: OrderInterface> {
     is_bid: bool,
     levels: BTreeMap<O::N, Level<O>>,
+    /// Resting orders with a discretionary price: discretion price -> level price -> count.
+    discretion: BTreeMap<O::N, BTreeMap<O::N, usize>>,
 }
 
 impl<O: OrderInterface> Side<O> {
@@ -18,6 +20,7 @@ impl<O: OrderInterface> Side<O> {
         Side {
             is_bid,
             levels: BTreeMap::new(),
+            discretion: BTreeMap::new(),
         }
     }
 
@@ -31,23 +34,69 @@ impl<O: OrderInterface> Side<O> {
         self.levels.is_empty()
     }
 
-    /// Returns the best price level (price, total_quantity).
-    /// For bids: highest price. For asks: lowest price.
+    /// Returns true if any resting order has a discretionary price.
+    #[inline(always)]
+    pub fn has_discretionary(&self) -> bool {
+        !self.discretion.is_empty()
+    }
+
+    /// Collects into `out` the prices of levels past `limit` holding an order whose
+    /// discretion reaches it, best price first.
     #[inline]
-    pub fn best(&self) -> Option<(O::N, O::N)> {
-        let (_, level) = if self.is_bid {
-            self.levels.last_key_value()?
+    pub fn reaching(&self, limit: O::N, out: &mut Vec<O::N>) {
+        let reach = if self.is_bid {
+            self.discretion.range(limit..)
         } else {
-            self.levels.first_key_value()?
+            self.discretion.range(..=limit)
         };
-        Some((level.price(), level.total_quantity()))
+        for prices in reach.map(|(_, prices)| prices) {
+            out.extend(prices.keys().copied().filter(|&price| {
+                (self.is_bid && price < limit) || (!self.is_bid && price > limit)
+            }));
+        }
+        if self.is_bid {
+            out.sort_unstable_by(|a, b| b.cmp(a));
+        } else {
+            out.sort_unstable();
+        }
+        out.dedup();
+    }
+
+    /// Returns the level at `price`, if any.
+    #[inline(always)]
+    pub fn level(&self, price: O::N) -> Option<&Level<O>> {
+        self.levels.get(&price)
+    }
+
+    /// Returns the best displayed price level (price, total_quantity).
+    /// For bids: highest price. For asks: lowest price.
+    #[inline]
+    pub fn best(&self) -> Option<(O::N, O::N)> {
+        self.iter()
+            .find(|level| level.is_displayed())
+            .map(|level| (level.price(), level.total_quantity()))
     }
 
-    /// Returns the top `n` price levels as (price, total_quantity).
+    /// Returns the best price displaying at least one unpegged order; hidden and
+    /// all-or-none orders do not count. This is the reference pegged orders follow.
+    #[inline]
+    pub fn best_unpegged(&self) -> Option<O::N> {
+        self.iter()
+            .find(|level| {
+                level.is_displayed()
+                    && level
+                        .iter_displayed()
+                        .any(|order| order.peg().is_none() && !order.all_or_none())
+            })
+            .map(|level| level.price())
+    }
+
+    /// Returns the top `n` displayed price levels as (price, total_quantity).
     /// For bids: highest prices first. For asks: lowest prices first.
     #[inline]
     pub fn top(&self, n: usize) -> Vec<(O::N, O::N)> {
         self.iter()
+            .filter(|level| level.is_displayed())
             .take(n)
             .map(|l| (l.price(), l.total_quantity()))
             .collect()
@@ -56,6 +105,14 @@ impl<O: OrderInterface> Side<O> {
     #[inline(always)]
     pub fn insert_order(&mut self, order: O, pool: &mut Pool<O>) -> *mut Node<O> {
         let price = order.price();
+        if let Some(reach) = order.discretion_price() {
+            *self
+                .discretion
+                .entry(reach)
+                .or_default()
+                .entry(price)
+                .or_default() += 1;
+        }
         self.levels
             .entry(price)
             .or_insert_with(|| Level::new(price))
@@ -69,31 +126,88 @@ impl<O: OrderInterface> Side<O> {
     pub fn fill_order(&mut self, node_ptr: *mut Node<O>, fill: O::N, pool: &mut Pool<O>) -> bool {
         let order = unsafe { &mut (*node_ptr).data };
         let price = order.price();
+        let reach = order.discretion_price();
         let btree_map::Entry::Occupied(mut entry) = self.levels.entry(price) else {
             unreachable!()
         };
         let level = entry.get_mut();
         let removed = level.fill_order(node_ptr, order, fill, pool);
+        if removed && let Some(reach) = reach {
+            Self::unindex_discretion(&mut self.discretion, reach, price);
+        }
         if level.is_empty() {
             entry.remove();
         }
         removed
     }
 
-    /// Removes an order by its node pointer.
+    /// Amends an order in place without losing queue position.
+    /// Caller must ensure node_ptr is valid and in this side, and that `remaining`
+    /// does not exceed the order's current remaining quantity.
+    #[inline(always)]
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn amend_order(&mut self, node_ptr: *mut Node<O>, quantity: O::N, remaining: O::N) {
+        let order = unsafe { &mut (*node_ptr).data };
+        let Some(level) = self.levels.get_mut(&order.price()) else {
+            unreachable!()
+        };
+        level.amend_order(order, quantity, remaining);
+    }
+
+    /// Reduces an order's remaining (and original) quantity by `quantity` in place.
+    /// Caller must ensure node_ptr is valid and in this side, and that `quantity` is
+    /// less than the order's remaining quantity.
+    #[inline(always)]
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn reduce_order(&mut self, node_ptr: *mut Node<O>, quantity: O::N) {
+        let (new_quantity, remaining) = unsafe {
+            let order = &(*node_ptr).data;
+            (order.quantity() - quantity, order.remaining() - quantity)
+        };
+        self.amend_order(node_ptr, new_quantity, remaining);
+    }
+
+    /// Removes an order by its node pointer, returning it.
     /// Caller must ensure node_ptr is valid and in this side.
     #[inline(always)]
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
-    pub fn remove_order(&mut self, node_ptr: *mut Node<O>, pool: &mut Pool<O>) {
+    pub fn remove_order(&mut self, node_ptr: *mut Node<O>, pool: &mut Pool<O>) -> Option<O> {
         let price = unsafe { (*node_ptr).data.price() };
         let btree_map::Entry::Occupied(mut entry) = self.levels.entry(price) else {
             unreachable!()
         };
         let level = entry.get_mut();
-        level.remove_order(node_ptr, pool);
+        let order = level.remove_order(node_ptr, pool);
         if level.is_empty() {
             entry.remove();
         }
+        if let Some(reach) = order.as_ref().and_then(|order| order.discretion_price()) {
+            Self::unindex_discretion(&mut self.discretion, reach, price);
+        }
+        order
+    }
+
+    /// Drops a discretionary order at `price` reaching `reach` from the index.
+    #[inline]
+    fn unindex_discretion(
+        discretion: &mut BTreeMap<O::N, BTreeMap<O::N, usize>>,
+        reach: O::N,
+        price: O::N,
+    ) {
+        let btree_map::Entry::Occupied(mut entry) = discretion.entry(reach) else {
+            unreachable!()
+        };
+        let prices = entry.get_mut();
+        let btree_map::Entry::Occupied(mut count) = prices.entry(price) else {
+            unreachable!()
+        };
+        *count.get_mut() -= 1;
+        if *count.get() == 0 {
+            count.remove();
+        }
+        if prices.is_empty() {
+            entry.remove();
+        }
     }
 
     #[inline]
@@ -160,7 +274,7 @@ impl<'a, O: OrderInterface> Iterator for LevelIterMut<'a, O> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::order::TestOrder;
+    use crate::order::{Peg, PegRef, TestOrder};
 
     #[test]
     fn test_new_side() {
@@ -197,6 +311,60 @@ mod tests {
         assert_eq!(side.height(), 3);
     }
 
+    #[test]
+    fn test_best_unpegged() {
+        let mut side = Side::<TestOrder>::new(true);
+        let mut pool = Pool::new();
+        assert_eq!(side.best_unpegged(), None);
+        let peg = Peg {
+            reference: PegRef::Primary,
+            offset: 1,
+            aggressive: true,
+            cap: None,
+        };
+        side.insert_order(TestOrder::new("1", true, 101, 10).with_peg(peg), &mut pool);
+        side.insert_order(TestOrder::new("2", true, 100, 10).with_peg(peg), &mut pool);
+        side.insert_order(TestOrder::new("3", true, 100, 10), &mut pool);
+        side.insert_order(TestOrder::new("4", true, 99, 10), &mut pool);
+        assert_eq!(side.best(), Some((101, 10)));
+        assert_eq!(side.best_unpegged(), Some(100));
+        // Hidden and all-or-none orders are not a reference.
+        side.insert_order(
+            TestOrder::new("5", true, 105, 10).with_hidden(true),
+            &mut pool,
+        );
+        side.insert_order(
+            TestOrder::new("6", true, 104, 10).with_all_or_none(true),
+            &mut pool,
+        );
+        assert_eq!(side.best_unpegged(), Some(100));
+    }
+
+    #[test]
+    fn test_reaching() {
+        let mut side = Side::<TestOrder>::new(false);
+        let mut pool = Pool::new();
+        side.insert_order(TestOrder::new("1", false, 100, 10), &mut pool);
+        let deep = TestOrder::new("2", false, 130, 10).with_discretion_price(104);
+        let deep = side.insert_order(deep, &mut pool);
+        let near = TestOrder::new("3", false, 110, 10).with_discretion_price(105);
+        side.insert_order(near, &mut pool);
+        side.insert_order(
+            TestOrder::new("4", false, 120, 10).with_discretion_price(108),
+            &mut pool,
+        );
+        let mut out = Vec::new();
+        side.reaching(105, &mut out);
+        assert_eq!(out, vec![110, 130]);
+        side.remove_order(deep, &mut pool);
+        out.clear();
+        side.reaching(105, &mut out);
+        assert_eq!(out, vec![110]);
+        out.clear();
+        side.reaching(103, &mut out);
+        assert!(out.is_empty());
+    }
+
     #[test]
     fn test_remove_order() {
         let mut side = Side::<TestOrder>::new(true);
@@ -217,6 +385,30 @@ mod tests {
         assert_eq!(level_count, 0);
     }
 
+    #[test]
+    fn test_amend_order() {
+        let mut side = Side::<TestOrder>::new(true);
+        let mut pool = Pool::new();
+        let node_ptr = side.insert_order(TestOrder::new("1", true, 100, 50), &mut pool);
+        side.amend_order(node_ptr, 30, 30);
+        assert_eq!(side.best(), Some((100, 30)));
+        let order = side.remove_order(node_ptr, &mut pool).unwrap();
+        assert_eq!(order.remaining(), 30);
+        assert!(side.is_empty());
+    }
+
+    #[test]
+    fn test_reduce_order() {
+        let mut side = Side::<TestOrder>::new(false);
+        let mut pool = Pool::new();
+        let node_ptr = side.insert_order(TestOrder::new("1", false, 100, 50), &mut pool);
+        side.insert_order(TestOrder::new("2", false, 100, 20), &mut pool);
+        side.reduce_order(node_ptr, 15);
+        assert_eq!(side.best(), Some((100, 55)));
+        let order = unsafe { &(*node_ptr).data };
+        assert_eq!((order.quantity(), order.remaining()), (35, 35));
+    }
+
     #[test]
     fn test_iter_bids() {
         let mut side = Side::<TestOrder>::new(true);
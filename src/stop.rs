@@ -0,0 +1,222 @@
+use crate::{
+    hash::FxHashMap,
+    order::{OrderInterface, TrailRef},
+};
+use std::collections::{BTreeMap, VecDeque, btree_map};
+
+/// Untriggered stop orders, kept off the bid/ask sides.
+///
+/// Buy stops trigger when the last trade price rises to or above their stop price;
+/// sell stops when it falls to or below. Triggered stops are released in stop-price
+/// order (nearest to the previous price first), FIFO within the same stop price.
+/// Trailing stops are kept apart in arrival order and re-priced on every update.
+pub struct Stops<O: OrderInterface> {
+    buys: BTreeMap<O::N, VecDeque<O::I>>,
+    sells: BTreeMap<O::N, VecDeque<O::I>>,
+    /// (Order ID, Current Trigger) — trigger is unset until a reference price exists.
+    trailing: Vec<(O::I, Option<O::N>)>,
+    orders: FxHashMap<O::I, O>,
+}
+
+impl<O: OrderInterface> Default for Stops<O> {
+    fn default() -> Self {
+        Self {
+            buys: BTreeMap::new(),
+            sells: BTreeMap::new(),
+            trailing: Vec::new(),
+            orders: FxHashMap::default(),
+        }
+    }
+}
+
+impl<O: OrderInterface> Stops<O> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn contains(&self, order_id: &O::I) -> bool {
+        !self.orders.is_empty() && self.orders.contains_key(order_id)
+    }
+
+    #[inline(always)]
+    pub fn has_trailing(&self) -> bool {
+        !self.trailing.is_empty()
+    }
+
+    #[inline]
+    pub fn get(&self, order_id: &O::I) -> Option<&O> {
+        self.orders.get(order_id)
+    }
+
+    /// Returns the current trigger price of a held stop.
+    pub fn trigger_price(&self, order_id: &O::I) -> Option<O::N> {
+        let order = self.orders.get(order_id)?;
+        if order.trail().is_none() {
+            return order.stop_price();
+        }
+        self.trailing
+            .iter()
+            .find(|(id, _)| id == order_id)
+            .and_then(|&(_, trigger)| trigger)
+    }
+
+    /// Holds a stop order. Caller must ensure `order.stop_price()` or `order.trail()` is set.
+    pub fn insert(&mut self, order: O) {
+        if order.trail().is_some() {
+            self.trailing.push((order.id().clone(), None));
+            self.orders.insert(order.id().clone(), order);
+            return;
+        }
+        let stop = order.stop_price().unwrap();
+        let book = if order.is_buy() {
+            &mut self.buys
+        } else {
+            &mut self.sells
+        };
+        book.entry(stop).or_default().push_back(order.id().clone());
+        self.orders.insert(order.id().clone(), order);
+    }
+
+    /// Removes a held stop order, returning it.
+    pub fn remove(&mut self, order_id: &O::I) -> Option<O> {
+        let order = self.orders.remove(order_id)?;
+        if order.trail().is_some() {
+            self.trailing.retain(|(id, _)| id != order_id);
+            return Some(order);
+        }
+        let stop = order.stop_price().unwrap();
+        let book = if order.is_buy() {
+            &mut self.buys
+        } else {
+            &mut self.sells
+        };
+        if let btree_map::Entry::Occupied(mut entry) = book.entry(stop) {
+            entry.get_mut().retain(|id| id != order_id);
+            if entry.get().is_empty() {
+                entry.remove();
+            }
+        }
+        Some(order)
+    }
+
+    /// Moves every stop triggered by a trade at `last` into `out`.
+    pub fn trigger(&mut self, last: O::N, out: &mut VecDeque<O>) {
+        while let Some(entry) = self.buys.first_entry() {
+            if *entry.key() > last {
+                break;
+            }
+            for id in entry.remove() {
+                out.push_back(self.orders.remove(&id).unwrap());
+            }
+        }
+        while let Some(entry) = self.sells.last_entry() {
+            if *entry.key() < last {
+                break;
+            }
+            for id in entry.remove() {
+                out.push_back(self.orders.remove(&id).unwrap());
+            }
+        }
+    }
+
+    /// Ratchets trailing stop triggers toward the current references and moves
+    /// triggered trailing stops into `out`, in arrival order.
+    pub fn update_trailing(
+        &mut self,
+        best_bid: Option<O::N>,
+        best_ask: Option<O::N>,
+        last: Option<O::N>,
+        out: &mut VecDeque<O>,
+    ) {
+        let zero = O::N::default();
+        let orders = &mut self.orders;
+        self.trailing.retain_mut(|(id, trigger)| {
+            let order = &orders[id];
+            let trail = order.trail().unwrap();
+            let is_buy = order.is_buy();
+            let reference = match trail.reference {
+                TrailRef::Best if is_buy => best_ask,
+                TrailRef::Best => best_bid,
+                TrailRef::LastTrade => last,
+            };
+            let Some(reference) = reference else {
+                return true;
+            };
+            let distance = trail.offset.distance(reference);
+            let price = if is_buy {
+                let candidate = reference + distance;
+                trigger.map_or(candidate, |t| t.min(candidate))
+            } else {
+                let candidate = if reference > distance {
+                    reference - distance
+                } else {
+                    zero
+                };
+                trigger.map_or(candidate, |t| t.max(candidate))
+            };
+            *trigger = Some(price);
+            let hit = (is_buy && reference >= price) || (!is_buy && reference <= price);
+            if hit {
+                out.push_back(orders.remove(id).unwrap());
+            }
+            !hit
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::TestOrder;
+
+    fn stop(id: &str, is_buy: bool, stop: u64) -> TestOrder {
+        TestOrder::new(id, is_buy, stop, 10).with_stop_price(stop)
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut stops = Stops::<TestOrder>::default();
+        assert!(stops.is_empty());
+        stops.insert(stop("1", true, 110));
+        stops.insert(stop("2", true, 110));
+        assert_eq!(stops.len(), 2);
+        assert!(stops.contains(&String::from("1")));
+        assert_eq!(stops.get(&String::from("2")).unwrap().price(), 110);
+
+        assert!(stops.remove(&String::from("1")).is_some());
+        assert!(stops.remove(&String::from("1")).is_none());
+        assert!(stops.remove(&String::from("2")).is_some());
+        assert!(stops.is_empty());
+        assert!(stops.buys.is_empty());
+    }
+
+    #[test]
+    fn test_trigger_order() {
+        let mut stops = Stops::<TestOrder>::default();
+        stops.insert(stop("b2", true, 105));
+        stops.insert(stop("b1", true, 101));
+        stops.insert(stop("b3", true, 105));
+        stops.insert(stop("b4", true, 120));
+        stops.insert(stop("s1", false, 90));
+        let mut out = VecDeque::new();
+
+        stops.trigger(100, &mut out);
+        assert!(out.is_empty());
+
+        stops.trigger(110, &mut out);
+        let ids: Vec<_> = out.drain(..).map(|o| o.id().clone()).collect();
+        assert_eq!(ids, vec!["b1", "b2", "b3"]);
+
+        stops.trigger(85, &mut out);
+        let ids: Vec<_> = out.drain(..).map(|o| o.id().clone()).collect();
+        assert_eq!(ids, vec!["s1"]);
+        assert_eq!(stops.len(), 1);
+    }
+}